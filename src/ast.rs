@@ -1,6 +1,8 @@
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
 
-use crate::token::Token;
+use crate::token::{Span, Token};
 
 #[derive(Debug,Clone)]
 pub enum Stmt {
@@ -21,29 +23,27 @@ pub enum Stmt {
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
     },
-    // while循环
+    // while循环，increment 为 for 循环 desugar 后的增量表达式
     While {
         condition: Expr,
         body: Box<Stmt>,
-    },
-    // for循环(后续会被desugar成while)
-    For {
-        initializer: Option<Box<Stmt>>,
-        condition: Option<Expr>,
         increment: Option<Expr>,
-        body: Box<Stmt>,
     },
-    // 函数声明
+    // 函数声明，参数和函数体用 Rc 共享，创建函数值、绑定方法时只复制指针
     Function {
         name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
+        params: Rc<[Token]>,
+        body: Rc<[Stmt]>,
     },
     // 返回语句
     Return {
         keyword: Token,
         value: Option<Expr>,
     },
+    // break语句
+    Break(Token),
+    // continue语句
+    Continue(Token),
     // 类声明
     Class {
         name: Token,
@@ -55,13 +55,20 @@ pub enum Stmt {
 #[derive(Debug, Clone)]
 pub enum Expr {
     // 基础字面量
-    Literal(Literal),
-    // 变量引用
-    Variable(Token),
+    Literal {
+        value: Literal,
+        span: Span,
+    },
+    // 变量引用，depth 为 resolver 计算出的作用域深度，None 表示全局变量
+    Variable {
+        name: Token,
+        depth: Cell<Option<usize>>,
+    },
     // 赋值表达式
     Assign {
         name: Token,
         value: Box<Expr>,
+        depth: Cell<Option<usize>>,
     },
     // 逻辑表达式
     Logical {
@@ -91,6 +98,12 @@ pub enum Expr {
         object: Box<Expr>,
         name: Token,
     },
+    // 下标访问
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
     // 属性设置
     Set {
         object: Box<Expr>,
@@ -99,10 +112,11 @@ pub enum Expr {
     },
     // this表达式
     This(Token),
-    // super表达式
+    // super表达式，depth 为 resolver 计算出的 super 所在作用域的深度
     Super {
         keyword: Token,
         method: Token,
+        depth: Cell<Option<usize>>,
     },
     // 分组表达式
     Grouping(Box<Expr>),
@@ -115,3 +129,71 @@ pub enum Literal {
     Bool(bool),
     Nil,
 }
+
+impl Expr {
+    /// 尚未解析的变量引用
+    pub fn variable(name: Token) -> Self {
+        Expr::Variable { name, depth: Cell::new(None) }
+    }
+
+    /// 表达式在源代码中的起始位置
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal { span, .. } => *span,
+            Expr::Variable { name, .. } | Expr::Assign { name, .. } => name.span,
+            Expr::Logical { left, .. } | Expr::Binary { left, .. } => left.span(),
+            Expr::Unary { operator, .. } => operator.span,
+            Expr::Call { callee, .. } => callee.span(),
+            Expr::Get { object, .. } | Expr::Set { object, .. } | Expr::Index { object, .. } => object.span(),
+            Expr::This(keyword) | Expr::Super { keyword, .. } => keyword.span,
+            Expr::Grouping(expr) => expr.span(),
+        }
+    }
+}
+
+/// 以前缀括号形式输出表达式，例如 `(+ 1 (* a 2))`，用于交互模式的 :ast 命令
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal { value, .. } => match value {
+                Literal::Number(n) => write!(f, "{}", n),
+                Literal::String(s) => write!(f, "\"{}\"", s),
+                Literal::Bool(b) => write!(f, "{}", b),
+                Literal::Nil => write!(f, "nil"),
+            },
+            Expr::Variable { name, .. } => write!(f, "{}", name.lexeme()),
+            Expr::Assign { name, value, .. } => write!(f, "(= {} {})", name.lexeme(), value),
+            Expr::Logical { left, operator, right } | Expr::Binary { left, operator, right } => {
+                write!(f, "({} {} {})", operator.lexeme(), left, right)
+            }
+            Expr::Unary { operator, right } => write!(f, "({} {})", operator.lexeme(), right),
+            Expr::Call { callee, arguments, .. } => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Get { object, name } => write!(f, "(. {} {})", object, name.lexeme()),
+            Expr::Index { object, index, .. } => write!(f, "([] {} {})", object, index),
+            Expr::Set { object, name, value } => write!(f, "(= (. {} {}) {})", object, name.lexeme(), value),
+            Expr::This(_) => write!(f, "this"),
+            Expr::Super { method, .. } => write!(f, "(super {})", method.lexeme()),
+            Expr::Grouping(expr) => write!(f, "(group {})", expr),
+        }
+    }
+}
+
+impl Stmt {
+    /// 语句在源代码中的起始位置
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr(expr) | Stmt::Print(expr) => expr.span(),
+            Stmt::Var { name, .. } | Stmt::Function { name, .. } | Stmt::Class { name, .. } => name.span,
+            Stmt::Block(stmts) => stmts.first().map(Stmt::span).unwrap_or_default(),
+            Stmt::If { condition, .. } | Stmt::While { condition, .. } => condition.span(),
+            Stmt::Return { keyword, .. } => keyword.span,
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => keyword.span,
+        }
+    }
+}
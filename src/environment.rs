@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::intepreter::Value;
+
+/// 作用域：当前作用域中定义的变量，以及指向外层作用域的指针
+#[derive(Default)]
+pub struct Environment {
+    values: HashMap<String, Option<Rc<RefCell<Value>>>>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    /// 全局作用域
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以 enclosing 为外层作用域创建新的作用域
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// 在当前作用域中定义（或重新定义）变量
+    pub fn define(&mut self, name: impl Into<String>, value: Option<Rc<RefCell<Value>>>) {
+        self.values.insert(name.into(), value);
+    }
+
+    /// 当前作用域中是否已经定义了该变量
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// 沿作用域链查找变量，用于没有经过 resolver 解析的全局变量
+    pub fn get(&self, name: &str) -> Option<Option<Rc<RefCell<Value>>>> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// 沿作用域链赋值，变量不存在时返回 false
+    pub fn assign(&mut self, name: &str, value: Option<Rc<RefCell<Value>>>) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+
+    /// 读取外面第 distance 层作用域中的变量
+    pub fn get_at(env: &Rc<RefCell<Environment>>, distance: usize, name: &str) -> Option<Option<Rc<RefCell<Value>>>> {
+        Self::ancestor(env, distance).borrow().values.get(name).cloned()
+    }
+
+    /// 给外面第 distance 层作用域中的变量赋值，变量不存在时返回 false
+    pub fn assign_at(env: &Rc<RefCell<Environment>>, distance: usize, name: &str, value: Option<Rc<RefCell<Value>>>) -> bool {
+        match Self::ancestor(env, distance).borrow_mut().values.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 外面第 distance 层作用域
+    fn ancestor(env: &Rc<RefCell<Environment>>, distance: usize) -> Rc<RefCell<Environment>> {
+        let mut current = env.clone();
+        for _ in 0..distance {
+            let enclosing = current.borrow().enclosing.clone()
+                .expect("resolver 计算出的作用域深度超出了作用域链");
+            current = enclosing;
+        }
+        current
+    }
+
+    /// 当前作用域中的所有变量
+    pub fn values(&self) -> impl Iterator<Item = (&String, &Option<Rc<RefCell<Value>>>)> {
+        self.values.iter()
+    }
+}
+
+/// 只输出变量名：函数值的 closure 指回定义它的作用域，输出值或外层作用域会无限递归
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.values.keys().collect();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("names", &names)
+            .finish_non_exhaustive()
+    }
+}
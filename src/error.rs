@@ -0,0 +1,205 @@
+use thiserror::Error;
+
+use std::fmt;
+
+use crate::token::{Span, Token, TokenKind};
+
+/// 对外暴露的解释器错误
+#[derive(Debug, Error)]
+pub enum LoxError {
+    // 读取源文件失败
+    #[error("Error reading file: {0}")]
+    Io(#[from] std::io::Error),
+    // 词法错误，一次报告全部
+    #[error("{}", join_lines(.0))]
+    Lex(Vec<LexError>),
+    // 语法错误，一次报告全部
+    #[error("{}", join_lines(.0))]
+    Parse(Vec<ParseError>),
+    // 静态解析错误，一次报告全部
+    #[error("{}", join_lines(.0))]
+    Resolve(Vec<ResolveError>),
+    // 运行时错误
+    #[error(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+impl LoxError {
+    /// 命令行使用的退出码（sysexits）
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LoxError::Io(_) => 74, // EX_IOERR
+            LoxError::Lex(_) | LoxError::Parse(_) | LoxError::Resolve(_) => 65, // EX_DATAERR
+            LoxError::Runtime(_) => 70, // EX_SOFTWARE
+        }
+    }
+}
+
+/// 每条错误占一行
+fn join_lines<T: std::fmt::Display>(errors: &[T]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+}
+
+/// 词法错误：出错位置和错误信息
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{span} Error: {message}")]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl LexError {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        LexError { span, message: message.into() }
+    }
+}
+
+/// 语法错误中期望出现的内容
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    Token(TokenKind), // 某个特定的 token
+    Identifier,       // 任意标识符
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Token(kind) => write!(f, "'{}'", kind),
+            Expected::Identifier => write!(f, "identifier"),
+        }
+    }
+}
+
+/// 语法错误：出错位置、期望的内容、实际遇到的 token 以及错误信息
+#[derive(Debug, Clone, PartialEq, Error)]
+pub struct ParseError {
+    pub span: Span,
+    pub expected: Option<Expected>, // 只有在等待某个特定 token 或标识符时才有
+    pub found: Option<String>, // 实际遇到的 token 的原文，源代码已经结束时为 None
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(token: &Token, expected: Option<Expected>, message: impl Into<String>) -> Self {
+        ParseError {
+            span: token.span,
+            expected,
+            found: (token.kind != TokenKind::Eof).then(|| token.lexeme.clone()),
+            message: message.into(),
+        }
+    }
+
+    /// 是否因为源代码提前结束而出错
+    pub fn at_end(&self) -> bool {
+        self.found.is_none()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            Some(lexeme) => write!(f, "{} Error at '{}': {}", self.span, lexeme, self.message),
+            None => write!(f, "{} Error at end: {}", self.span, self.message),
+        }
+    }
+}
+
+/// 静态解析错误：语法正确但在作用域上不合法的代码，例如顶层的 return
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{} Error at '{}': {message}", .token.span, .token.lexeme)]
+pub struct ResolveError {
+    pub token: Token, // 出错位置的 token
+    pub message: String,
+}
+
+impl ResolveError {
+    pub fn new(token: &Token, message: impl Into<String>) -> Self {
+        ResolveError { token: token.clone(), message: message.into() }
+    }
+}
+
+/// 运行时错误的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    Type,              // 操作数类型不匹配
+    UndefinedVariable, // 未定义的变量
+    UndefinedProperty, // 未定义的属性
+    NotCallable,       // 调用了函数和类以外的值
+    NotAnInstance,     // 在实例以外的值上访问属性
+    Arity,             // 实参个数与形参不一致
+    DivisionByZero,    // 除以零
+    Index,             // 下标越界
+    StackOverflow,     // 递归过深
+    Native,            // 内置函数报告的错误
+}
+
+/// Lox 调用栈中的一帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: String, // 被调用的函数名
+    pub line: usize,      // 调用发生的行号
+}
+
+/// 运行时错误：通过 Result 逐层向上传播，由宿主决定如何处理
+#[derive(Debug, Clone, Error)]
+#[error("RuntimeError: {message}")]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub message: String,
+    pub lexeme: Option<String>, // 出错位置 token 的原文
+    pub line: usize,
+    pub column: usize,
+    pub trace: Vec<TraceFrame>, // 调用栈，最内层的调用在前
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, token: Option<&Token>, message: impl Into<String>) -> Self {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            lexeme: token.map(|t| t.lexeme.clone()),
+            line: token.map_or(0, |t| t.line()),
+            column: token.map_or(0, |t| t.column()),
+            trace: Vec::new(),
+        }
+    }
+
+    /// 完整的错误报告：错误信息、出错位置以及调用栈
+    pub fn report(&self) -> String {
+        let mut report = format!("{}\n[line {}:{}]", self, self.line, self.column);
+        // 每一帧输出该函数中正在执行的行，最外层为顶层脚本
+        let caller = |index: usize| match self.trace.get(index) {
+            Some(frame) => format!("{}()", frame.function),
+            None => "script".to_string(),
+        };
+        report.push_str(&format!(" in {}", caller(0)));
+        // 连续相同的帧合并为一行，记录重复次数
+        let mut frames: Vec<(String, usize)> = Vec::new();
+        for (index, frame) in self.trace.iter().enumerate() {
+            let line = format!("[line {}] in {}", frame.line, caller(index + 1));
+            match frames.last_mut() {
+                Some((last, count)) if *last == line => *count += 1,
+                _ => frames.push((line, 1)),
+            }
+        }
+        // 帧太多时只输出最内层和最外层的各 TRACE_EDGE 帧
+        let hidden = frames.len().saturating_sub(2 * TRACE_EDGE);
+        for (index, (line, count)) in frames.iter().enumerate() {
+            if hidden > 0 && index >= TRACE_EDGE && index < frames.len() - TRACE_EDGE {
+                if index == TRACE_EDGE {
+                    let skipped: usize = frames[TRACE_EDGE..TRACE_EDGE + hidden].iter().map(|(_, count)| count).sum();
+                    report.push_str(&format!("\n... {} more frames", skipped));
+                }
+                continue;
+            }
+            report.push_str(&format!("\n{}", line));
+            if *count > 1 {
+                report.push_str(&format!("\n[previous frame repeated {} more times]", count - 1));
+            }
+        }
+        report
+    }
+}
+
+/// 调用栈过长时，错误报告中最内层和最外层各保留的帧数
+const TRACE_EDGE: usize = 10;
@@ -1,28 +1,20 @@
-use lox_interpreter::{lexer::Lexer, parser::Parser, Expr, Stmt,Literal};
-use lox_interpreter::token::Token;
+use crate::ast::{Expr, Literal, Stmt};
+use crate::environment::Environment;
+use crate::error::{LoxError, RuntimeError, RuntimeErrorKind, TraceFrame};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::strings;
+use crate::token::{Token, TokenKind};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::hash::Hash;
-use rand::{distributions::Alphanumeric, Rng};
+use std::fmt;
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
-use lazy_static::lazy_static;
-use std::sync::Mutex;
-use std::sync::atomic::{AtomicBool, Ordering};
-
-fn gen_string(length: usize) -> String {//生成随机字符串
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric) // 包括 A-Z, a-z, 0-9
-        .take(length)
-        .map(char::from)
-        .collect()
-}
-
-pub static GLOBAL_ERR: AtomicBool = AtomicBool::new(false);
-pub static GLOBAL_FUNC: AtomicBool = AtomicBool::new(false);
-pub static GLOBAL_CLASS: AtomicBool = AtomicBool::new(false);
-pub static GLOBAL_CALL: AtomicBool = AtomicBool::new(false);
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub enum Value {//值类型
     Number(f64),
     String(String),
@@ -30,881 +22,800 @@ pub enum Value {//值类型
     Nil,
     Null,
     Function {//函数
-        name: Framelist,
-        frame: HashMap<(String,String),Option<Rc<RefCell<Value>>>>,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
+        closure: Rc<RefCell<Environment>>,//定义函数时所在的作用域
+        params: Rc<[Token]>,
+        body: Rc<[Stmt]>,
         obj_bind: Option<Rc<RefCell<Value>>>,
-        class_def: Option<String>,
         func_name: String,
+        is_initializer: bool,//是否为类的 init 方法，调用后总是返回 this
     },
-    Classdef {//类定义
-        name: String,
-        superclass: String,
-        methods: Vec<Stmt>,
-    },
-    Instance {//实例
+    List(Rc<RefCell<Vec<Value>>>),//列表，由字符串的 split 方法创建
+    Class(Rc<LoxClass>),//类
+    Instance(Rc<RefCell<LoxInstance>>),//实例，多个值共享同一个对象
+    Native {//内置函数
         name: String,
-        fields: HashMap<(String,String), Option<Rc<RefCell<Value>>>>,
+        arity: usize,
+        func: NativeFn,
     },
 }
 
-#[derive(Debug, Clone)]
-pub struct Framelist{//作用域链
-    pub next: Option<Box<Framelist>>,
-    pub frame: String,
+/// 运行时的类：方法表在声明类时建立一次，所有实例共用
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,//基类
+    methods: HashMap<String, Value>,//本类定义的方法，尚未绑定 this
+}
+
+impl LoxClass {
+    /// 查找方法：先在本类中查找，找不到时沿基类向上查找
+    pub fn find_method(&self, name: &str) -> Option<&Value> {
+        match self.methods.get(name) {
+            Some(method) => Some(method),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+/// 实例：只保存字段，方法通过所属的类查找
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    pub fields: HashMap<String, Option<Rc<RefCell<Value>>>>,
+}
+
+/// 只输出类名和字段名：字段可能引用实例自身
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields: Vec<&String> = self.fields.keys().collect();
+        fields.sort();
+        f.debug_struct("LoxInstance")
+            .field("class", &self.class.name)
+            .field("fields", &fields)
+            .finish()
+    }
+}
+
+/// 内置函数的实现：可以访问解释器，出错时返回运行时错误
+pub type NativeFnImpl = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError>;
+
+/// 内置函数的实现，包装一层以便 Value 实现 Debug 和 Clone
+#[derive(Clone)]
+pub struct NativeFn(pub Rc<NativeFnImpl>);
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+/// 值的输出形式，print、字符串拼接和 str() 都使用它
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(num) => fmt_number(*num, f),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Nil | Value::Null => write!(f, "nil"),
+            Value::Function { func_name, .. } => write!(f, "<fn {}>", func_name),
+            Value::Native { .. } => write!(f, "<native fn>"),
+            Value::List(list) => {
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+        }
+    }
+}
+
+/// 调试输出：函数不输出 closure（其中又保存着函数本身），类和实例只输出名字
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(num) => f.debug_tuple("Number").field(num).finish(),
+            Value::String(s) => f.debug_tuple("String").field(s).finish(),
+            Value::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Value::Nil => write!(f, "Nil"),
+            Value::Null => write!(f, "Null"),
+            Value::Function { func_name, .. } => write!(f, "<fn {}>", func_name),
+            Value::Native { name, .. } => write!(f, "<native fn {}>", name),
+            Value::List(list) => f.debug_list().entries(list.borrow().iter()).finish(),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Instance(instance) => write!(f, "<{} instance>", instance.borrow().class.name),
+        }
+    }
+}
+
+impl Value {
+    /// 值在 Lox 中的字符串形式
+    pub fn to_lox_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// 数字的输出形式：整数不带小数部分，无穷大和 NaN 与 Lox 的写法一致
+fn fmt_number(num: f64, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if num.is_nan() {
+        write!(f, "NaN")
+    } else if num.is_infinite() {
+        write!(f, "{}Infinity", if num < 0.0 { "-" } else { "" })
+    } else {
+        write!(f, "{}", num)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Ret{//返回值
-    pub exit:bool,//是否需要退出当前函数
+    pub exit:bool,//是否需要退出当前函数或循环
+    pub jump: Option<Jump>,//由 break/continue 引起的退出
     pub value: Option<Rc<RefCell<Value>>>,
 }
 
-pub fn traverse_statements(statements: &Vec<Stmt>,depth: usize,
-    map: &mut HashMap<(String,String), Option<Rc<RefCell<Value>>>>,
-    env: Framelist,obj :Option<Rc<RefCell<Value>>>,cur_class: Option<String>) ->Ret{//遍历多条语句
-    for stmt in statements {
-        let val: Ret = traverse_stmt(stmt,depth,map,env.clone(),obj.clone(),cur_class.clone());
-        if val.exit {
-            return val;
-        }
+/// 循环中的跳转
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    Break,
+    Continue,
+}
+
+/// Lox 函数调用的最大嵌套层数，超过时报告 Stack overflow 运行时错误
+const MAX_CALL_DEPTH: usize = 1000;
+/// 剩余栈空间少于 STACK_RED_ZONE 时，为下一层语句或表达式分配 STACK_SEGMENT_SIZE 大小的新栈段
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// 解释器：对外的嵌入接口，main.rs 与外部 crate 都通过它执行 Lox 代码
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,//全局作用域
+    call_stack: Vec<TraceFrame>,//当前的 Lox 调用栈
+}
+
+impl fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("globals", &*self.globals.borrow())
+            .field("call_stack", &self.call_stack)
+            .finish()
     }
-    Ret {
-        exit: false,
-        value: Some(Rc::new(RefCell::new(Value::Nil))),
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub fn traverse_stmt(stmt: &Stmt,depth: usize,map: &mut HashMap<(String,String), Option<Rc<RefCell<Value>>>>,
-    env: Framelist,obj :Option<Rc<RefCell<Value>>>,cur_class: Option<String>) -> Ret{ //遍历单条语句
-    if GLOBAL_ERR.load(Ordering::SeqCst) == true {
-        return Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
-            }; // 如果已经有错误，直接返回None
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut interpreter = Interpreter {
+            globals: Rc::new(RefCell::new(Environment::new())),
+            call_stack: Vec::new(),
+        };
+        interpreter.define_builtins();
+        interpreter
     }
-    match stmt {
-        Stmt::Expr(expr) => {//表达式语句
-            traverse_expr(expr,depth+1,map,env,obj.clone(),cur_class.clone());
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
-            }
+
+    /// 在全局作用域中注册内置函数，已有同名的全局变量时覆盖它
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        let native = Value::Native {
+            name: name.to_string(),
+            arity,
+            func: NativeFn(Rc::new(func)),
+        };
+        self.globals.borrow_mut().define(name, Some(Rc::new(RefCell::new(native))));
+    }
+
+    /// 标准内置函数
+    fn define_builtins(&mut self) {
+        // clock()：从 Unix 纪元开始经过的秒数
+        self.define_native("clock", 0, |_, _| {
+            let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Ok(Value::Number(elapsed.as_secs_f64()))
+        });
+        // str(value)：把任意值转换为字符串
+        self.define_native("str", 1, |_, args| Ok(Value::String(args[0].to_lox_string())));
+    }
+
+    /// 执行一段源代码
+    pub fn run_source(&mut self, source: &str) -> Result<(), LoxError> {
+        let tokens = Self::scan(source)?;
+        let statements = Parser::new(&tokens).parse().map_err(LoxError::Parse)?;
+        Resolver::new().resolve(&statements).map_err(LoxError::Resolve)?;
+        self.execute(&statements)
+    }
+
+    /// 交互模式下执行一段输入：输入恰好是一个表达式（没有分号）时输出它的值
+    pub fn run_repl_input(&mut self, source: &str) -> Result<(), LoxError> {
+        let tokens = Self::scan(source)?;
+        let statements = Parser::new(&tokens).parse_repl().map_err(LoxError::Parse)?;
+        Resolver::new().resolve(&statements).map_err(LoxError::Resolve)?;
+        self.execute(&statements)
+    }
+
+    /// 当前所有全局绑定的名字和值，按名字排序
+    pub fn globals(&self) -> Vec<(String, String)> {
+        let mut globals: Vec<(String, String)> = self.globals.borrow().values()
+            .map(|(name, value)| {
+                let value = match value {
+                    Some(value) => value.borrow().to_lox_string(),
+                    None => "nil".to_string(),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        globals.sort();
+        globals
+    }
+
+    /// 词法分析，有词法错误时一次返回全部
+    fn scan(source: &str) -> Result<Vec<Token>, LoxError> {
+        let (tokens, errors) = Lexer::new(source).scan_tokens();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(LoxError::Lex(errors))
         }
-        Stmt::Print(expr) => {//打印语句
-            let value: Option<Rc<RefCell<Value>>> = traverse_expr(expr,depth+1,map,env,obj.clone(),cur_class.clone());
-            if GLOBAL_ERR.load(Ordering::SeqCst) == true {
-                return Ret {
-                    exit: false,
-                    value: Some(Rc::new(RefCell::new(Value::Nil))),
-                }; // 如果已经有错误，直接返回None
-            }
-            match value {
-                Some(ref rc_value) => {
-                    let value = rc_value.borrow();
-                    match &*value {
-                        Value::Number(num) => println!("{}", num),
-                        Value::String(s) => println!("{}", s),
-                        Value::Bool(b) => println!("{}", b),
-                        Value::Nil => println!("nil"),
-                        Value::Function { name, frame, params, body, obj_bind, class_def, func_name } => {
-                            println!("<fn {}>", func_name);
-                        }
-                        _ => println!("Unknown value"),
-                    }
-                },
-                None => {
-                    println!("Error: PrintStmt requires a value");
-                    GLOBAL_ERR.store(true, Ordering::SeqCst);
-                }
-            }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
+    }
+
+    fn execute(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        // 每次执行都从干净的状态开始，上一次的错误不会影响本次执行
+        self.call_stack.clear();
+        let globals = self.globals.clone();
+        self.traverse_statements(statements, globals, None)?;
+        Ok(())
+    }
+
+    /// 读取并执行一个源文件
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoxError> {
+        let source = fs::read_to_string(path)?;
+        self.run_source(&source)
+    }
+
+    /// 构造运行时错误，并附上当前的调用栈
+    fn error(&self, kind: RuntimeErrorKind, token: Option<&Token>, message: impl Into<String>) -> RuntimeError {
+        let mut err = RuntimeError::new(kind, token, message);
+        err.trace = self.call_stack.iter().rev().cloned().collect();
+        err
+    }
+
+    fn traverse_statements(&mut self, statements: &[Stmt],
+        env: Rc<RefCell<Environment>>,obj :Option<Rc<RefCell<Value>>>) -> Result<Ret, RuntimeError>{//遍历多条语句
+        for stmt in statements {
+            let val: Ret = self.traverse_stmt(stmt,env.clone(),obj.clone())?;
+            if val.exit {
+                return Ok(val);
             }
         }
-        Stmt::Var { name, initializer } => {//变量声明语句
-            let var_name = name.lexeme().to_string();
-            let frame = env.clone().frame;
-            let key = (var_name.clone(), frame.clone());
-
-            if map.contains_key(&key) {
-                println!("Error: Already a variable with this name in this scope.");
-                GLOBAL_ERR.store(true, Ordering::SeqCst);
-            }
+        Ok(Ret {
+            exit: false,
+            jump: None,
+            value: Some(Rc::new(RefCell::new(Value::Nil))),
+        })
+    }
 
-            if let Some(expr) = initializer {
-                let value: Option<Rc<RefCell<Value>>> = traverse_expr(expr,depth+1,map,env.clone(),obj.clone(),cur_class.clone());
-                map.insert(key, value);
-            }
-            else{
-                map.insert(key, Some(Rc::new(RefCell::new(Value::Nil))));
-            }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
+    fn traverse_stmt(&mut self, stmt: &Stmt,
+        env: Rc<RefCell<Environment>>,obj :Option<Rc<RefCell<Value>>>) -> Result<Ret, RuntimeError>{ //遍历单条语句
+        // 嵌套的语句和表达式以及 Lox 函数调用都会在这里递归，栈空间不足时在堆上分配新的栈段继续执行
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || match stmt {
+            Stmt::Expr(expr) => {//表达式语句
+                self.traverse_expr(expr,env,obj.clone())?;
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
             }
-        }
-        Stmt::Block(stmts) => {//块语句
-            let mut new_map: HashMap<(String,String), Option<Rc<RefCell<Value>>>> = map.clone();
-            let new_env = gen_string(15);
-            let new_frame = Framelist {
-                next: Some(Box::new(env)),
-                frame: new_env.clone(),
-            };
-            let mut flag: bool=false;
-            for s in stmts {
-                let Ret: Ret=traverse_stmt(s,depth+1,&mut new_map,new_frame.clone(),obj.clone(),cur_class.clone());
-                if Ret.exit {
-                    flag=true;
-                    break;
+            Stmt::Print(expr) => {//打印语句
+                let value: Option<Rc<RefCell<Value>>> = self.traverse_expr(expr,env,obj.clone())?;
+                match value {
+                    Some(ref rc_value) => println!("{}", rc_value.borrow()),
+                    None => println!("nil"),
                 }
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
             }
-            for (key, value) in new_map {
-                map.insert(key, value);
+            Stmt::Var { name, initializer } => {//变量声明语句
+                let var_name = name.lexeme();
+                let value = match initializer {
+                    Some(expr) => self.traverse_expr(expr,env.clone(),obj.clone())?,
+                    None => Some(Rc::new(RefCell::new(Value::Nil))),
+                };
+                env.borrow_mut().define(var_name, value);
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
             }
-            Ret {
-                exit: flag,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
+            Stmt::Block(stmts) => {//块语句
+                let block_env = Rc::new(RefCell::new(Environment::with_enclosing(env)));
+                self.traverse_statements(stmts, block_env, obj.clone())
             }
-        }
-        Stmt::If { condition, then_branch, else_branch } => {//条件语句
-            let cond: Option<Rc<RefCell<Value>>> = traverse_expr(condition, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            if let Some(ref rc_cond) = cond {
-                let cond_value = rc_cond.borrow();
-                if let Value::Bool(true) = &*cond_value {
-                    let ret: Ret = traverse_stmt(then_branch, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
+            Stmt::If { condition, then_branch, else_branch } => {//条件语句
+                let cond: Option<Rc<RefCell<Value>>> = self.traverse_expr(condition, env.clone(), obj.clone())?;
+                if is_truthy_option(&cond) {
+                    let ret: Ret = self.traverse_stmt(then_branch, env.clone(), obj.clone())?;
                     if ret.exit {
-                        return ret;
-                    }
-                } 
-                else if let Value::String(s) = &*cond_value {
-                    let ret: Ret = traverse_stmt(then_branch, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-                    if ret.exit {
-                        return ret;
+                        return Ok(ret);
                     }
                 }
-                else {
-                    if let Some(else_branch) = else_branch {
-                        let ret: Ret = traverse_stmt(else_branch, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-                        if ret.exit {
-                            return ret;
-                        }
+                else if let Some(else_branch) = else_branch {
+                    let ret: Ret = self.traverse_stmt(else_branch, env.clone(), obj.clone())?;
+                    if ret.exit {
+                        return Ok(ret);
                     }
                 }
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
             }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
-            }
-        }
-        Stmt::While { condition, body } => {//while循环语句
-            loop {
-                let cond: Option<Rc<RefCell<Value>>> = traverse_expr(condition, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-                if let Some(ref rc_cond) = cond {
-                    let cond_value = rc_cond.borrow();
-                    if let Value::Bool(false) = &*cond_value {
+            Stmt::While { condition, body, increment } => {//while循环语句
+                loop {
+                    let cond: Option<Rc<RefCell<Value>>> = self.traverse_expr(condition, env.clone(), obj.clone())?;
+                    if !is_truthy_option(&cond) {
                         break;
                     }
-                }
-                let ret: Ret = traverse_stmt(body, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-                if ret.exit {
-                    return ret;
-                }
-            }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
-            }
-        }
-        Stmt::For { initializer, condition, increment, body } => {//for循环语句
-            let mut new_map: HashMap<(String,String), Option<Rc<RefCell<Value>>>> = map.clone();
-            if let Some(init) = initializer {
-                traverse_stmt(init,depth + 1,&mut new_map,env.clone(), obj.clone(), cur_class.clone());
-            }
-            loop{
-                if let Some(cond) = condition {
-                    let cond = traverse_expr(cond,depth + 1,&mut new_map,env.clone(), obj.clone(), cur_class.clone());
-                    if let Some(ref rc_cond) = cond {
-                        let cond_value = rc_cond.borrow();
-                        if let Value::Bool(false) = &*cond_value {
-                            break;
+                    let ret: Ret = self.traverse_stmt(body, env.clone(), obj.clone())?;
+                    if ret.exit {
+                        match ret.jump {
+                            Some(Jump::Break) => break,
+                            Some(Jump::Continue) => {}
+                            None => return Ok(ret),
                         }
                     }
-                }
-                let ret: Ret = traverse_stmt(body, depth + 1, &mut new_map, env.clone(), obj.clone(), cur_class.clone());
-                if ret.exit {
-                    return ret;
-                }
-                if let Some(inc) = increment {
-                    traverse_expr(inc, depth + 1, &mut new_map, env.clone(), obj.clone(), cur_class.clone());
-                }
-            }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
-            }
-        }
-        Stmt::Function { name, params, body } => {//函数声明语句
-            let func: Value = Value::Function {
-                frame: map.clone(),
-                params: params.clone(),
-                body: body.clone(),
-                name: env.clone(),
-                obj_bind: obj.clone(),
-                class_def: cur_class.clone(),
-                func_name: name.lexeme().to_string(),
-            };
-            map.insert((name.lexeme().to_string(), env.frame.clone()), Some(Rc::new(RefCell::new(func.clone()))));
-            if let Some(obj_ref) = obj {
-                if let Value::Instance { name: instance_name, fields } = &mut *obj_ref.borrow_mut() {
-                    if let Some(cur_class_name) = cur_class {
-                        fields.insert((name.lexeme().to_string(), cur_class_name.clone()), Some(Rc::new(RefCell::new(func))));
-                    } else {
-                        println!("Error: Current class name is None");
+                    if let Some(inc) = increment {
+                        self.traverse_expr(inc, env.clone(), obj.clone())?;
                     }
                 }
-            }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
-            }
-        }
-        Stmt::Return { keyword, value } => {//返回语句
-            if GLOBAL_FUNC.load(Ordering::SeqCst) == false {
-                println!("Error: Can't return from top-level code.");
-                GLOBAL_ERR.store(true, Ordering::SeqCst);
-            }
-            if let Some(expr) = value {
-                let val: Option<Rc<RefCell<Value>>> = traverse_expr(expr,depth + 1,map,env.clone(),obj.clone(),cur_class.clone());
-                return Ret {
-                    exit: true,
-                    value: val,
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
+            }
+            Stmt::Function { name, params, body } => {//函数声明语句
+                let func: Value = Value::Function {
+                    closure: env.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    obj_bind: obj.clone(),
+                    func_name: name.lexeme().to_string(),
+                    is_initializer: false,
                 };
-            }
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Null))),
-            }
-        }
-        Stmt::Class { name, superclass, methods } => {//类声明语句
-            let newclass: Value = Value::Classdef {
-                name: name.lexeme().to_string(),
-                superclass: if let Some(superclass_expr) = superclass {
-                    if let Expr::Variable(token) = superclass_expr {
-                        if let Expr::Variable(token) = superclass_expr {
-                            traverse_expr( superclass_expr, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-                            if GLOBAL_ERR.load(Ordering::SeqCst) == true {
-                                String::new() // 如果已经有错误，返回空字符串
-                            }
-                            else{
-                                token.lexeme().to_string()
-                            }
-                        } else {
-                            println!("Error: Superclass must be a variable");
-                            String::new()
+                env.borrow_mut().define(name.lexeme(), Some(Rc::new(RefCell::new(func))));
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
+            }
+            Stmt::Return { value, .. } => {//返回语句
+                if let Some(expr) = value {
+                    let val: Option<Rc<RefCell<Value>>> = self.traverse_expr(expr,env.clone(),obj.clone())?;
+                    return Ok(Ret {
+                        exit: true,
+                        jump: None,
+                        value: val,
+                    });
+                }
+                // 没有返回值的 return 同样结束函数，返回 nil
+                Ok(Ret {
+                    exit: true,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
+            }
+            Stmt::Break(_) => Ok(Ret {//break语句
+                exit: true,
+                jump: Some(Jump::Break),
+                value: None,
+            }),
+            Stmt::Continue(_) => Ok(Ret {//continue语句
+                exit: true,
+                jump: Some(Jump::Continue),
+                value: None,
+            }),
+            Stmt::Class { name, superclass, methods } => {//类声明语句
+                // 基类在声明时求值，类中保存的是基类对象本身，之后重新定义同名变量不影响继承关系
+                let superclass = match superclass {
+                    Some(superclass_expr @ Expr::Variable { name: super_name, .. }) => {
+                        let value = self.traverse_expr(superclass_expr, env.clone(), obj.clone())?;
+                        match value.as_ref().map(|value| value.borrow().clone()) {
+                            Some(Value::Class(class)) => Some(class),
+                            _ => return Err(self.error(RuntimeErrorKind::Type, Some(super_name), "Superclass must be a class.")),
                         }
-                    } else {
-                        println!("Error: Superclass must be a variable");
-                        String::new()
                     }
-                } else {
-                    String::new()
-                },
-                methods: methods.clone(),
-            };
-            map.insert((name.lexeme().to_string(), env.frame.clone()), Some(Rc::new(RefCell::new(newclass))));
-            Ret {
-                exit: false,
-                value: Some(Rc::new(RefCell::new(Value::Nil))),
+                    _ => None,
+                };
+                // 有基类时，方法的外层作用域中多一层，其中的 super 指向基类
+                let method_env = match &superclass {
+                    Some(superclass) => {
+                        let mut super_env = Environment::with_enclosing(env.clone());
+                        super_env.define("super", Some(Rc::new(RefCell::new(Value::Class(superclass.clone())))));
+                        Rc::new(RefCell::new(super_env))
+                    }
+                    None => env.clone(),
+                };
+                // 方法表只在声明时建立一次
+                let mut class_methods: HashMap<String, Value> = HashMap::new();
+                for method in methods {
+                    if let Stmt::Function { name: method_name, params, body } = method {
+                        let func = Value::Function {
+                            closure: method_env.clone(),
+                            params: params.clone(),
+                            body: body.clone(),
+                            obj_bind: None,
+                            func_name: method_name.lexeme().to_string(),
+                            is_initializer: method_name.lexeme() == "init",
+                        };
+                        class_methods.insert(method_name.lexeme().to_string(), func);
+                    }
+                }
+                let newclass = Value::Class(Rc::new(LoxClass {
+                    name: name.lexeme().to_string(),
+                    superclass,
+                    methods: class_methods,
+                }));
+                env.borrow_mut().define(name.lexeme(), Some(Rc::new(RefCell::new(newclass))));
+                Ok(Ret {
+                    exit: false,
+                    jump: None,
+                    value: Some(Rc::new(RefCell::new(Value::Nil))),
+                })
             }
-        }
+        })
     }
-}
 
-pub fn traverse_expr(expr: &Expr,depth: usize,map: &mut HashMap<(String,String), Option<Rc<RefCell<Value>>>>,env: Framelist,
-    obj :Option<Rc<RefCell<Value>>>,cur_class: Option<String>) -> Option<Rc<RefCell<Value>>> {
-    if depth > 3000{
-        GLOBAL_ERR.store(true, Ordering::SeqCst);
-        println!("RuntimeError: Stack overflow.");
-    }
-    if GLOBAL_ERR.load(Ordering::SeqCst) == true {
-        return Some(Rc::new(RefCell::new(Value::Nil))); // 如果已经有错误，直接返回None
-    }
-    match expr {
-        Expr::Literal(literal) => {//字面量表达式
-            let val: Value = traverse_literal(literal, depth + 1);
-            Some(Rc::new(RefCell::new(val)))
-        }
-        Expr::Variable(token) => {//变量表达式
-            let mut cur_env: Framelist = env.clone();
-            loop {
-                if let Some(value) = map.get(&(token.lexeme().to_string(), cur_env.frame.clone())) {
-                    return value.clone();
-                }
-                match &cur_env.next {
-                    Some(next) => cur_env = (**next).clone(),
-                    None => break, // No more environments to check
-                }
-            }
-            if GLOBAL_CLASS.load(Ordering::SeqCst) == true {
-                println!("RuntimeError: Undefined property '{}'.", token.lexeme());
-                GLOBAL_ERR.store(true, Ordering::SeqCst);
-            } else {
-                println!("RuntimeError: Undefined variable '{}'.", token.lexeme());
-                GLOBAL_ERR.store(true, Ordering::SeqCst);
-            }
-            Some(Rc::new(RefCell::new(Value::Nil))) // Return Nil if variable not found
-        }
-        Expr::Assign { name, value } => {//赋值表达式
-            let value: Option<Rc<RefCell<Value>>> = traverse_expr(value,depth+1,map,env.clone(),obj.clone(),cur_class.clone());
-            let mut cur_env: Framelist = env.clone();
-            let old_frame:Framelist;
-            loop {
-                if let Some(value_local) = map.get(&(name.lexeme().to_string(), cur_env.frame.clone())) {
-                    old_frame = cur_env.clone();
-                    map.insert((name.lexeme().to_string(), old_frame.frame), value);
-                    break;
-                }
-                match &cur_env.next {
-                    Some(next) => cur_env = (**next).clone(),
-                    None => break,
+    fn traverse_expr(&mut self, expr: &Expr,env: Rc<RefCell<Environment>>,
+        obj :Option<Rc<RefCell<Value>>>) -> Result<Option<Rc<RefCell<Value>>>, RuntimeError> {
+        // 栈空间不足时在堆上分配新的栈段继续执行，见 traverse_stmt
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || match expr {
+            Expr::Literal { value: literal, .. } => {//字面量表达式
+                let val: Value = traverse_literal(literal);
+                Ok(Some(Rc::new(RefCell::new(val))))
+            }
+            Expr::Variable { name: token, depth: scope_depth } => {//变量表达式
+                // 局部变量按 resolver 计算的深度直接定位，其余的在全局作用域中查找
+                let value = match scope_depth.get() {
+                    Some(distance) => Environment::get_at(&env, distance, token.lexeme()),
+                    None => self.globals.borrow().get(token.lexeme()),
+                };
+                if let Some(value) = value {
+                    return Ok(value);
                 }
-            }
-            Some(Rc::new(RefCell::new(Value::Number(0.0)))) // Return Nil after assignment
-        }
-        Expr::Logical { left, operator, right } => {//逻辑表达式
-            let left_value: Option<Rc<RefCell<Value>>> = traverse_expr(left, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            let right_value: Option<Rc<RefCell<Value>>> = traverse_expr(right, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            let mut result: Option<Rc<RefCell<Value>>> = None;
-            match operator.lexeme() {
-                "or" => {
-                    if let Some(ref rc_left) = left_value {
-                        let left_value = rc_left.borrow();
-                        if let Value::Bool(true) = &*left_value {
-                            result = Some(Rc::new(RefCell::new(left_value.clone())));
-                        }
-                    }
-                    if result.is_none() {
-                        result = right_value;
-                    }
+                Err(self.error(RuntimeErrorKind::UndefinedVariable, Some(token),
+                    format!("Undefined variable '{}'.", token.lexeme())))
+            }
+            Expr::Assign { name, value, depth: scope_depth } => {//赋值表达式
+                let value: Option<Rc<RefCell<Value>>> = self.traverse_expr(value,env.clone(),obj.clone())?;
+                let assigned = match scope_depth.get() {
+                    Some(distance) => Environment::assign_at(&env, distance, name.lexeme(), value.clone()),
+                    None => self.globals.borrow_mut().assign(name.lexeme(), value.clone()),
+                };
+                if !assigned {
+                    return Err(self.error(RuntimeErrorKind::UndefinedVariable, Some(name),
+                        format!("Undefined variable '{}'.", name.lexeme())));
                 }
-                "and" => {
-                    if let Some(ref rc_left) = left_value {
-                        let left_value = rc_left.borrow();
-                        if let Value::Bool(false) = &*left_value {
-                            result = Some(Rc::new(RefCell::new(left_value.clone())));
-                        } else {
-                            result = right_value;
-                        }
-                    }
+                // 赋值表达式的值就是被赋的值，支持 a = b = 3 这样的连续赋值
+                Ok(value)
+            }
+            Expr::Logical { left, operator, right } => {//逻辑表达式
+                // 短路求值：左操作数能决定结果时不再计算右操作数，结果为决定结果的那个操作数
+                let left_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(left, env.clone(), obj.clone())?;
+                let left_truthy = is_truthy_option(&left_value);
+                let decided = match operator.kind {
+                    TokenKind::Or => left_truthy,
+                    _ => !left_truthy,
+                };
+                if decided {
+                    return Ok(left_value);
                 }
-                _ => println!("  Result: Unknown logical operation"),
-            }
-            result
-        }
-        Expr::Binary { left, operator, right } => {//二元运算表达式
-            let left_value: Option<Rc<RefCell<Value>>> = traverse_expr(left,depth+1,map,env.clone(),obj.clone(),cur_class.clone());
-            let right_value: Option<Rc<RefCell<Value>>> = traverse_expr(right,depth+1,map,env.clone(),obj.clone(),cur_class.clone());
-            let mut result: Option<Rc<RefCell<Value>>> = Some(Rc::new(RefCell::new(Value::Number(0.0))));
-            let mut isnumber: bool = false;
-            let mut type_conflict: bool = false;
-            let left_num = match left_value {
-                Some(ref rc_left) => {
-                    let left_value = rc_left.borrow();
-                    match &*left_value {
-                        Value::Number(num) => {
-                            isnumber = true;
-                            *num
-                        },
-                        _ => 0.0,
+                self.traverse_expr(right, env.clone(), obj.clone())
+            }
+            Expr::Binary { left, operator, right } => {//二元运算表达式
+                let left_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(left,env.clone(),obj.clone())?;
+                let right_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(right,env.clone(),obj.clone())?;
+                let nil = Rc::new(RefCell::new(Value::Nil));
+                let left_rc = left_value.unwrap_or_else(|| nil.clone());
+                let right_rc = right_value.unwrap_or(nil);
+                let left_ref = left_rc.borrow();
+                let right_ref = right_rc.borrow();
+                let result: Value = match (operator.lexeme(), &*left_ref, &*right_ref) {
+                    ("+", Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+                    // 有一侧是字符串时，另一侧按 print 的形式转换为字符串再拼接
+                    ("+", l @ Value::String(_), r) | ("+", l, r @ Value::String(_)) => Value::String(format!("{}{}", l, r)),
+                    ("-", Value::Number(l), Value::Number(r)) => Value::Number(l - r),
+                    ("*", Value::Number(l), Value::Number(r)) => Value::Number(l * r),
+                    ("/", Value::Number(_), Value::Number(r)) if *r == 0.0 => {
+                        return Err(self.error(RuntimeErrorKind::DivisionByZero, Some(operator), "Division by zero."));
                     }
-                },
-                None => 0.0,
-            };
-            let right_num = match right_value {
-                Some(ref rc_right) => {
-                    let right_value = rc_right.borrow();
-                    match &*right_value {
-                        Value::Number(num) => {
-                            isnumber = true;
-                            *num
-                        },
-                        _ => 0.0,
+                    ("/", Value::Number(l), Value::Number(r)) => Value::Number(l / r),
+                    ("+" | "-" | "*" | "/", _, _) => {
+                        return Err(self.error(RuntimeErrorKind::Type, Some(operator),
+                            "Operands must be two numbers or two strings."));
                     }
-                },
-                None => 0.0,
-            };
-            let left_string = match left_value {
-                Some(ref rc_left) => {
-                    let left_value = rc_left.borrow();
-                    match &*left_value {
-                        Value::String(s) => {
-                            if isnumber == true{
-                                type_conflict = true;
-                            }
-                            s.clone()
-                        },
-                        _ => String::new(),
-                    }
-                },
-                None => String::new(),
-            };
-            let right_string = match right_value {
-                Some(ref rc_right) => {
-                    let right_value = rc_right.borrow();
-                    match &*right_value {
-                        Value::String(s) => {
-                            if isnumber == true{
-                                type_conflict = true;
-                            }
-                            s.clone()
-                        },
-                        _ => String::new(),
+                    (">", Value::Number(l), Value::Number(r)) => Value::Bool(l > r),
+                    ("<", Value::Number(l), Value::Number(r)) => Value::Bool(l < r),
+                    (">=", Value::Number(l), Value::Number(r)) => Value::Bool(l >= r),
+                    ("<=", Value::Number(l), Value::Number(r)) => Value::Bool(l <= r),
+                    (">" | "<" | ">=" | "<=", _, _) => {
+                        return Err(self.error(RuntimeErrorKind::Type, Some(operator), "Operands must be numbers."));
                     }
-                },
-                None => String::new(),
-            };
-            if type_conflict == true {
-                println!("RuntimeError: Operands must be two numbers or two strings.");
-                GLOBAL_ERR.store(true, Ordering::SeqCst);
-            }
-            match operator.lexeme() {
-                "+" => {
-                    if isnumber==true{
-                        result = Some(Rc::new(RefCell::new(Value::Number(left_num + right_num))));
-                    } else {
-                        result = Some(Rc::new(RefCell::new(Value::String(format!("{}{}", left_string, right_string)))));
+                    ("==", l, r) => Value::Bool(values_equal(l, r)),
+                    (_, l, r) => Value::Bool(!values_equal(l, r)),
+                };
+                Ok(Some(Rc::new(RefCell::new(result))))
+            }
+            Expr::Unary { operator, right } => {//一元运算表达式
+                let value: Option<Rc<RefCell<Value>>> = self.traverse_expr(right,env.clone(),obj.clone())?;
+                let value = value.map(|v| v.borrow().clone()).unwrap_or(Value::Nil);
+                let result = match (operator.lexeme(), &value) {
+                    ("-", Value::Number(n)) => Value::Number(-n),
+                    ("-", _) => {
+                        return Err(self.error(RuntimeErrorKind::Type, Some(operator), "Operand must be a number."));
                     }
-                }
-                "-" => result = Some(Rc::new(RefCell::new(Value::Number(left_num - right_num)))),
-                "*" => result = Some(Rc::new(RefCell::new(Value::Number(left_num * right_num)))),
-                "/" => {
-                    if right_num != 0.0 {
-                        result = Some(Rc::new(RefCell::new(Value::Number(left_num / right_num))));
+                    (_, value) => Value::Bool(!is_truthy(value)),
+                };
+                Ok(Some(Rc::new(RefCell::new(result))))
+            }
+            Expr::Call { callee, paren, arguments } => {//调用表达式
+                let func: Option<Rc<RefCell<Value>>> = self.traverse_expr(callee, env.clone(), obj.clone())?;
+                let mut args: Vec<Value> = Vec::new();
+                for arg in arguments {
+                    let value = self.traverse_expr(arg, env.clone(), obj.clone())?;
+                    if let Some(ref rc_value) = value {
+                        args.push(rc_value.borrow().clone());
                     } else {
-                        GLOBAL_ERR.store(true, Ordering::SeqCst);
-                        println!("RuntimeError: Division by zero.");
+                        args.push(Value::Nil);
                     }
                 }
-                ">" => {
-                    result = Some(Rc::new(RefCell::new(Value::Bool(left_num > right_num))));
-                }
-                "<" => {
-                    result = Some(Rc::new(RefCell::new(Value::Bool(left_num < right_num))));
-                }
-                ">=" => {
-                    result = Some(Rc::new(RefCell::new(Value::Bool(left_num >= right_num))));
-                }
-                "<=" => {
-                    result = Some(Rc::new(RefCell::new(Value::Bool(left_num <= right_num))));
-                }
-                "==" => {
-                    result=match (left_value, right_value) {
-                        (Some(ref rc_left), Some(ref rc_right)) => {
-                            let left_value = rc_left.borrow();
-                            let right_value = rc_right.borrow();
-                            match (&*left_value, &*right_value) {
-                                (Value::Number(l), Value::Number(r)) => Some(Rc::new(RefCell::new(Value::Bool(l == r)))),
-                                (Value::String(l), Value::String(r)) => Some(Rc::new(RefCell::new(Value::Bool(l == r)))),
-                                (Value::Bool(l), Value::Bool(r)) => Some(Rc::new(RefCell::new(Value::Bool(l == r)))),
-                                (Value::Nil, Value::Nil) => Some(Rc::new(RefCell::new(Value::Bool(true)))),
-                                _ => Some(Rc::new(RefCell::new(Value::Bool(false)))),
-                            }
-                        },
-                        _ => Some(Rc::new(RefCell::new(Value::Bool(false)))),
-                    };
-                }
-                "!=" => {
-                    result=match (left_value, right_value) {
-                        (Some(ref rc_left), Some(ref rc_right)) => {
-                            let left_value = rc_left.borrow();
-                            let right_value = rc_right.borrow();
-                            match (&*left_value, &*right_value) {
-                                (Value::Number(l), Value::Number(r)) => Some(Rc::new(RefCell::new(Value::Bool(l != r)))),
-                                (Value::String(l), Value::String(r)) => Some(Rc::new(RefCell::new(Value::Bool(l != r)))),
-                                (Value::Bool(l), Value::Bool(r)) => Some(Rc::new(RefCell::new(Value::Bool(l != r)))),
-                                (Value::Nil, Value::Nil) => Some(Rc::new(RefCell::new(Value::Bool(false)))),
-                                _ => Some(Rc::new(RefCell::new(Value::Bool(true)))),
-                            }
-                        },
-                        _ => Some(Rc::new(RefCell::new(Value::Bool(true)))),
-                    };
-                }
-                _ => println!("  Result: Unknown operation"),
-            };
-            result
-        }
-        Expr::Unary { operator, right } => {//一元运算表达式
-            let value: Option<Rc<RefCell<Value>>> = traverse_expr(right, depth + 1,map,env.clone(),obj.clone(),cur_class.clone());
-            let mut result: Option<Rc<RefCell<Value>>> = Some(Rc::new(RefCell::new(Value::Number(0.0))));
-            let num = match value {
-                Some(ref rc_value) => {
-                    let value = rc_value.borrow();
-                    match &*value {
-                        Value::Number(n) => *n,
-                        Value::Bool(b) => if *b { 1.0 } else { 0.0 },
-                        _ => 0.0, // Default to 0.0 for other types
+                let rc_func = match func {
+                    Some(rc_func) => rc_func,
+                    None => return Err(self.error(RuntimeErrorKind::NotCallable, Some(paren),
+                        "Can only call functions and classes.")),
+                };
+                let func = rc_func.borrow();
+                match &*func {
+                    Value::Function { params, func_name, .. } => {//函数调用
+                        if params.len() != args.len() {
+                            return Err(self.error(RuntimeErrorKind::Arity, Some(paren),
+                                format!("Expected {} arguments but got {}.", params.len(), args.len())));
+                        }
+                        self.call_stack.push(TraceFrame { function: func_name.clone(), line: paren.line() });
+                        let retval = self.call_function(&func, args, paren);
+                        self.call_stack.pop();
+                        retval
                     }
-                },
-                None => {
-                    0.0
-                },
-            };
-            match operator.lexeme() {
-                "-" => result = Some(Rc::new(RefCell::new(Value::Number(-num)))),
-                "!" => {
-                    if num==1.0 {
-                        result = Some(Rc::new(RefCell::new(Value::Bool(false))));
-                    } else {
-                        result = Some(Rc::new(RefCell::new(Value::Bool(true))));
+                    Value::Class(class) => {//类调用
+                        // 参数个数与 init 一致，没有 init 时不接受参数
+                        let arity = match class.find_method("init") {
+                            Some(Value::Function { params, .. }) => params.len(),
+                            _ => 0,
+                        };
+                        if arity != args.len() {
+                            return Err(self.error(RuntimeErrorKind::Arity, Some(paren),
+                                format!("Expected {} arguments but got {}.", arity, args.len())));
+                        }
+                        self.call_stack.push(TraceFrame { function: class.name.clone(), line: paren.line() });
+                        let result = self.instantiate(class.clone(), args, paren);
+                        self.call_stack.pop();
+                        result
                     }
-                },
-                _ => println!("  Result: Unknown unary operation"),
-            }
-            result
-        }
-        Expr::Call { callee, paren, arguments } => {//调用表达式
-            let func: Option<Rc<RefCell<Value>>> = traverse_expr(callee, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            let mut args: Vec<Value> = Vec::new();
-            for arg in arguments {
-                let value = traverse_expr(arg, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-                if let Some(ref rc_value) = value {
-                    args.push(rc_value.borrow().clone());
-                } else {
-                    args.push(Value::Nil);
-                }
-            }
-            match func {
-                Some(ref rc_func) => {
-                    let func = rc_func.borrow();
-                    match &*func {
-                        Value::Function { frame, params, body, name ,obj_bind,class_def, func_name} => {//函数调用
-                            // Create a new environment for the function call
-                            GLOBAL_FUNC.store(true, Ordering::SeqCst);
-                            if params.len() != args.len() {
-                                println!(
-                                    "RuntimeError: Expected {} arguments but got {}.",
-                                    params.len(),
-                                    args.len()
-                                );
-                                GLOBAL_ERR.store(true, Ordering::SeqCst);
-                            }
-                            let mut call_frame = frame.clone();
-                            for(k,v) in map.iter() {
-                                call_frame.insert((k.0.clone(), k.1.clone()), v.clone());
-                            }
-                            let new_env = gen_string(15);
-                            for (param, arg) in params.iter().zip(args) {
-                                call_frame.insert((param.lexeme().to_string(), new_env.clone()), Some(Rc::new(RefCell::new(arg))));
-                            }
-                            let new_frame = Framelist {
-                                next: Some(Box::new(name.clone())),
-                                frame: new_env.clone(),
-                            };
-                            let retval: Ret= traverse_statements(&body, depth + 1, &mut call_frame, new_frame,obj_bind.clone(),class_def.clone());
-                            for(k,v) in call_frame.iter() {
-                                map.insert((k.0.clone(), k.1.clone()), v.clone());
-                            }
-                            GLOBAL_FUNC.store(false, Ordering::SeqCst);
-                            return retval.value;
+                    Value::Native { name, arity, func } => {//内置函数调用
+                        if *arity != args.len() {
+                            return Err(self.error(RuntimeErrorKind::Arity, Some(paren),
+                                format!("Expected {} arguments but got {}.", arity, args.len())));
                         }
-                        Value::Classdef { name, superclass, methods } => {//类调用
-                            GLOBAL_CLASS.store(true, Ordering::SeqCst);
-                            let new_field: HashMap<(String,String), Option<Rc<RefCell<Value>>>>= HashMap::new();
-                            let instance_name = name.clone();
-                            let instance = Value::Instance {
-                                name: instance_name.clone(),
-                                fields: new_field,
-                            };
-                            let new_instance: Option<Rc<RefCell<Value>>> = Some(Rc::new(RefCell::new(instance.clone())));
-                            let mut cur_name=name.clone();
-                            loop {//load all methods from class and superclass
-                                let mut methods_to_traverse=Vec::new();
-                                let mut next_class=String::new();
-                                {
-                                    let mut cur_class = map.get(&(cur_name.clone(), env.frame.clone()));
-                                    let mut cur_env: Framelist = env.clone();
-                                    loop {
-                                        if let Some(value) = map.get(&(cur_name.clone(), cur_env.frame.clone())) {
-                                            cur_class = map.get(&(cur_name.clone(), cur_env.frame.clone()));
-                                        }
-                                        match &cur_env.next {
-                                            Some(next) => cur_env = (**next).clone(),
-                                            None => break, // No more environments to check
-                                        }
-                                    }
-                                    if let Some(valueref)=cur_class{
-                                        if let Some(ref methods) = valueref{
-                                            let class_= methods.borrow();
-                                            match &*class_ {
-                                                Value::Classdef { name: _, superclass: next, methods: m } => {
-                                                    next_class = next.clone();
-                                                    methods_to_traverse = m.clone();
-                                                }
-                                                _ => {
-                                                    println!("Error: Expected a class definition");
-                                                    next_class = String::new();
-                                                    methods_to_traverse = Vec::new();
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                traverse_statements(&methods_to_traverse, depth + 1, map, env.clone(), new_instance.clone(), Some(cur_name.clone()));
-                                if next_class.is_empty() {
-                                    break;
-                                } else {
-                                    cur_name = next_class;
-                                }
+                        let func = func.0.clone();
+                        self.call_stack.push(TraceFrame { function: name.clone(), line: paren.line() });
+                        let result = func(self, &args).map_err(|mut err| {
+                            // 内置函数没有给出位置时，报告在调用处
+                            if err.line == 0 {
+                                err.lexeme = Some(paren.lexeme().to_string());
+                                err.line = paren.line();
+                                err.column = paren.column();
                             }
-                            //call init method if exists
-                            cur_name=name.clone();
-                            loop{
-                                let mut flag:bool = false;
-                                let mut frame_func:HashMap<(String,String), Option<Rc<RefCell<Value>>>> = HashMap::new();
-                                let mut func_env_tmp:Framelist = Framelist { next: None, frame: env.frame.clone() };
-                                let mut body_func:Vec<Stmt> = Vec::new();
-                                let mut param_func: Vec<Token> = Vec::new();
-                                if let Some(ref rc_inst)=new_instance{
-                                    let inst = rc_inst.borrow();
-                                    match &*inst {
-                                        Value::Instance { name, fields } => {
-                                            // Call the init method if it exists
-                                            let init: String="init".to_string();
-                                            if let Some(init_method) = fields.get(&(init, cur_name.clone())) {//if init method exists
-                                                if let Some(ref rc_func)=init_method{
-                                                    let final_func= rc_func.borrow();
-                                                    if let Value::Function { frame, params, body, name: func_env, obj_bind: _ , class_def: _ , func_name } = &*final_func {
-                                                        // Create a new environment for the init call
-                                                        frame_func = frame.clone();
-                                                        func_env_tmp=func_env.clone();
-                                                        body_func = body.clone();
-                                                        param_func = params.clone();
-                                                        flag = true;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                if flag {
-                                    let mut call_frame = frame_func.clone();
-                                    for(k,v) in map.iter() {
-                                        call_frame.insert((k.0.clone(), k.1.clone()), v.clone());
-                                    }
-                                    let new_env = gen_string(15);
-                                    for (param, arg) in param_func.iter().zip(args) {
-                                        call_frame.insert((param.lexeme().to_string(), new_env.clone()), Some(Rc::new(RefCell::new(arg))));
-                                    }
-                                    let new_frame = Framelist {
-                                        next: Some(Box::new(func_env_tmp.clone())),
-                                        frame: new_env,
-                                    };
-                                    traverse_statements(&body_func, depth + 1, &mut call_frame, new_frame, new_instance.clone(), Some(cur_name.clone()));
-                                    for(k,v) in call_frame.iter() {
-                                        map.insert((k.0.clone(), k.1.clone()), v.clone());
-                                    }
-                                    return new_instance;
-                                }
-                                let mut next_class = String::new();
-                                {
-                                    let mut cur_class = map.get(&(cur_name.clone(), env.frame.clone()));
-                                    let mut cur_env: Framelist = env.clone();
-                                    loop {
-                                        if let Some(value) = map.get(&(cur_name.clone(), cur_env.frame.clone())) {
-                                            cur_class = map.get(&(cur_name.clone(), cur_env.frame.clone()));
-                                        }
-                                        match &cur_env.next {
-                                            Some(next) => cur_env = (**next).clone(),
-                                            None => break, // No more environments to check
-                                        }
-                                    }
-                                    if let Some(valueref)=cur_class{
-                                        if let Some(ref methods) = valueref{
-                                            let class_= methods.borrow();
-                                            match &*class_ {
-                                                Value::Classdef { name: _, superclass: next, methods: m } => {
-                                                    next_class = next.clone();
-                                                }
-                                                _ => {
-                                                    println!("Error: Expected a class definition");
-                                                    next_class = String::new();
-                                                }
-                                            }
-                                        }
-                                    } else {
-                                        next_class = String::new();
-                                    }
-                                }
-                                if next_class.is_empty() {
-                                    break;
-                                } else {
-                                    cur_name = next_class;
-                                }
+                            if err.trace.is_empty() {
+                                err.trace = self.call_stack.iter().rev().cloned().collect();
                             }
-                            GLOBAL_CLASS.store(false, Ordering::SeqCst);
-                            return new_instance;
-                        }
-                        _ => {
-                            if GLOBAL_ERR.load(Ordering::SeqCst) == false {
-                                println!("RuntimeError: Can only call functions and classes.");
-                                GLOBAL_ERR.store(true, Ordering::SeqCst);
-                            }
-                        }
+                            err
+                        });
+                        self.call_stack.pop();
+                        Ok(Some(Rc::new(RefCell::new(result?))))
                     }
-                    Option::Some(Rc::new(RefCell::new(Value::Nil))) // Return Nil if not a function or class
+                    _ => Err(self.error(RuntimeErrorKind::NotCallable, Some(paren),
+                        "Can only call functions and classes.")),
                 }
-                _ => Option::Some(Rc::new(RefCell::new(Value::Nil)))
-            }
-        }
-        Expr::This(token) => {//this表达式
-            if GLOBAL_CLASS.load(Ordering::SeqCst) == false {
-                println!("Error: Can't use 'this' outside of a class.");
-                GLOBAL_ERR.store(true, Ordering::SeqCst);
             }
-            obj
-        }
-        Expr::Get { object, name } => {//属性访问表达式
-            let obj_value: Option<Rc<RefCell<Value>>> = traverse_expr(&object, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            match obj_value {
-                Some(rc_value) => {
-                    let value = rc_value.borrow();
-                    if let Value::Instance { name: instance_name, fields } = &*value {
-                        let mut cur_name=instance_name.clone();
-                        loop {
-                            if let Some(field_value) = fields.get(&(name.lexeme().to_string(), cur_name.clone())) {
-                                return field_value.clone();
-                            }
-                            let mut next_class = String::new();
-                            {
-                                let cur_class = map.get(&(cur_name.clone(), env.frame.clone()));
-                                if let Some(valueref)=cur_class{
-                                    if let Some(ref methods) = valueref{
-                                        let class_= methods.borrow();
-                                        match &*class_ {
-                                            Value::Classdef { name: _, superclass: next, methods: _ } => {
-                                                next_class = next.clone();
-                                            }
-                                            _ => {
-                                                println!("Error: Expected a class definition");
-                                                next_class = String::new();
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    next_class = String::new();
-                                }
-                            }
-                            if next_class.is_empty() {
-                                break;
-                            } else {
-                                cur_name = next_class;
-                            }
+            Expr::This(_) => {//this表达式
+                // resolver 保证 this 只出现在方法中，obj 即为方法绑定的实例
+                Ok(obj)
+            }
+            Expr::Get { object, name } => {//属性访问表达式
+                let obj_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(object, env.clone(), obj.clone())?;
+                if let Some(rc_value) = obj_value {
+                    if let Value::Instance(instance) = &*rc_value.borrow() {
+                        // 先查找字段，再查找方法；方法在访问时绑定 this
+                        let instance = instance.borrow();
+                        if let Some(field_value) = instance.fields.get(name.lexeme()) {
+                            return Ok(field_value.clone());
+                        }
+                        if let Some(method) = instance.class.find_method(name.lexeme()) {
+                            return Ok(Some(Rc::new(RefCell::new(bind(method, rc_value.clone())))));
                         }
-                        GLOBAL_ERR.store(true, Ordering::SeqCst);
-                        println!("RuntimeError: Undefined property '{}'.", name.lexeme());
+                        return Err(self.error(RuntimeErrorKind::UndefinedProperty, Some(name),
+                            format!("Undefined property '{}'.", name.lexeme())));
+                    }
+                    // 字符串和列表的内置方法
+                    let builtin = match &*rc_value.borrow() {
+                        Value::String(s) => Some(strings::string_method(s, name.lexeme())),
+                        Value::List(list) => Some(strings::list_method(list, name.lexeme())),
+                        _ => None,
+                    };
+                    if let Some(method) = builtin {
+                        return match method {
+                            Some(method) => Ok(Some(Rc::new(RefCell::new(method)))),
+                            None => Err(self.error(RuntimeErrorKind::UndefinedProperty, Some(name),
+                                format!("Undefined property '{}'.", name.lexeme()))),
+                        };
                     }
                 }
-                None => println!("Error: GetExpr can only be used on instances"),
-            }
-            Some(Rc::new(RefCell::new(Value::Nil))) // Return Nil if property not found
-        }
-        Expr::Set { object, name, value } => {//属性设置表达式
-            let obj_value: Option<Rc<RefCell<Value>>> = traverse_expr(&object, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            let new_value: Option<Rc<RefCell<Value>>> = traverse_expr(&value, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            //let obj_value_clone= obj_value.clone();
-            if let Some(rc_obj) = obj_value {
-                let mut obj_borrow = rc_obj.borrow_mut();
-                if let Value::Instance { name: instance_name, ref mut fields } = &mut *obj_borrow {
-                    fields.insert((name.lexeme().to_string(), instance_name.clone()), new_value.clone());
-                } else {
-                    println!("Error: SetExpr can only be used on instances");
+                Err(self.error(RuntimeErrorKind::NotAnInstance, Some(name), "Only instances have properties."))
+            }
+            Expr::Index { object, bracket, index } => {//下标访问表达式
+                let obj_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(object, env.clone(), obj.clone())?;
+                let index_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(index, env.clone(), obj.clone())?;
+                let obj_value = obj_value.map(|v| v.borrow().clone()).unwrap_or(Value::Nil);
+                let index_value = index_value.map(|v| v.borrow().clone()).unwrap_or(Value::Nil);
+                match strings::index(&obj_value, &index_value) {
+                    Ok(value) => Ok(Some(Rc::new(RefCell::new(value)))),
+                    Err(err) => Err(self.error(err.kind, Some(bracket), err.message)),
                 }
             }
-            Some(Rc::new(RefCell::new(Value::Nil))) // Return Nil after setting the property
-        }
-        Expr::Super { keyword, method } => {//super表达式
-            let mut cur_env: Framelist = env.clone();
-            let mut current_class=map.get(&(cur_class.clone().unwrap_or_default(), env.frame.clone()));
-            loop {
-                if let Some(value) = map.get(&(cur_class.clone().unwrap_or_default(), cur_env.frame.clone())) {
-                    current_class=map.get(&(cur_class.clone().unwrap_or_default(), cur_env.frame.clone()));
-                }
-                match &cur_env.next {
-                    Some(next) => cur_env = (**next).clone(),
-                    None => break, // No more environments to check
+            Expr::Set { object, name, value } => {//属性设置表达式
+                let obj_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(object, env.clone(), obj.clone())?;
+                let new_value: Option<Rc<RefCell<Value>>> = self.traverse_expr(value, env.clone(), obj.clone())?;
+                if let Some(rc_obj) = obj_value {
+                    if let Value::Instance(instance) = &*rc_obj.borrow() {
+                        instance.borrow_mut().fields.insert(name.lexeme().to_string(), new_value.clone());
+                        return Ok(new_value);
+                    }
                 }
+                Err(self.error(RuntimeErrorKind::NotAnInstance, Some(name), "Only instances have fields."))
             }
-            if let Some(current_class_ref) = current_class {
-                if let Some(ref rc_current_class) = current_class_ref {
-                    let current_class_value = rc_current_class.borrow();
-                    if let Value::Classdef { name: class_name, superclass: super_name, methods } = &*current_class_value {
-                        let mut cur_name=super_name.clone();
-                        loop{
-                            if cur_name.is_empty() {
-                                break;
-                            }
-                            match obj{
-                                Some(ref rc_obj) => {
-                                    let obj_borrow = rc_obj.borrow();
-                                    if let Value::Instance { name: instance_name, fields } = &*obj_borrow {
-                                        if let Some(value) = fields.get(&(method.lexeme().to_string(), cur_name.clone())) {
-                                            return value.clone();
-                                        }
-                                    }
-                                }
-                                None => {}
-                            }
-                            let mut next_class = String::new();
-                            {
-                                let cur_class = map.get(&(cur_name.clone(), env.frame.clone()));
-                                if let Some(valueref)=cur_class{
-                                    if let Some(ref methods) = valueref{
-                                        let class_= methods.borrow();
-                                        match &*class_ {
-                                            Value::Classdef { name: _, superclass: next, methods: m } => {
-                                                next_class = next.clone();
-                                            }
-                                            _ => {
-                                                println!("Error: Can't use 'super' in a class with no superclass.");
-                                                next_class = String::new();
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    next_class = String::new();
-                                }
-                            }
-                            cur_name = next_class;
-                        }
+            Expr::Super { keyword, method, depth: scope_depth } => {//super表达式
+                // resolver 保证 super 只出现在有基类的类中，按计算出的深度找到声明类时保存的基类
+                let superclass = match scope_depth.get() {
+                    Some(distance) => Environment::get_at(&env, distance, keyword.lexeme()).flatten(),
+                    None => None,
+                };
+                let superclass = match superclass.as_ref().map(|value| value.borrow().clone()) {
+                    Some(Value::Class(class)) => Some(class),
+                    _ => None,
+                };
+                // 从基类开始沿继承链查找方法，并绑定到当前的 this
+                if let (Some(superclass), Some(this)) = (superclass, obj) {
+                    if let Some(found) = superclass.find_method(method.lexeme()) {
+                        return Ok(Some(Rc::new(RefCell::new(bind(found, this)))));
                     }
                 }
+                Err(self.error(RuntimeErrorKind::UndefinedProperty, Some(method),
+                    format!("Undefined property '{}'.", method.lexeme())))
+            }
+            Expr::Grouping(expr) => {//分组表达式（括号内表达式）
+                self.traverse_expr(expr, env.clone(), obj.clone())
             }
-            Some(Rc::new(RefCell::new(Value::Nil)))
+        })
+    }
+
+    /// 调用 Lox 函数：以定义函数时的作用域为外层作用域绑定参数，然后执行函数体
+    ///
+    /// 参数个数由调用方检查；init 方法无论如何返回都以绑定的实例作为返回值
+    ///
+    /// 调用层数超过 MAX_CALL_DEPTH 时报告 Stack overflow，错误位置为调用处的右括号
+    fn call_function(&mut self, function: &Value, args: Vec<Value>, paren: &Token) -> Result<Option<Rc<RefCell<Value>>>, RuntimeError> {
+        let Value::Function { closure, params, body, obj_bind, is_initializer, .. } = function else {
+            return Ok(None);
+        };
+        if self.call_stack.len() > MAX_CALL_DEPTH {
+            return Err(self.error(RuntimeErrorKind::StackOverflow, Some(paren), "Stack overflow."));
+        }
+        let call_env = Rc::new(RefCell::new(Environment::with_enclosing(closure.clone())));
+        for (param, arg) in params.iter().zip(args) {
+            call_env.borrow_mut().define(param.lexeme(), Some(Rc::new(RefCell::new(arg))));
+        }
+        let retval = self.traverse_statements(body, call_env, obj_bind.clone())?;
+        if *is_initializer {
+            return Ok(obj_bind.clone());
+        }
+        Ok(retval.value)
+    }
+
+    /// 创建类的实例，然后调用 init
+    fn instantiate(&mut self, class: Rc<LoxClass>, args: Vec<Value>, paren: &Token) -> Result<Option<Rc<RefCell<Value>>>, RuntimeError> {
+        let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance {
+            class: class.clone(),
+            fields: HashMap::new(),
+        })));
+        let new_instance: Rc<RefCell<Value>> = Rc::new(RefCell::new(instance));
+        if let Some(init) = class.find_method("init") {
+            self.call_function(&bind(init, new_instance.clone()), args, paren)?;
         }
-        Expr::Grouping(expr) => {//分组表达式（括号内表达式）
-            let val: Option<Rc<RefCell<Value>>> = traverse_expr(&expr, depth + 1, map, env.clone(), obj.clone(), cur_class.clone());
-            val
+        Ok(Some(new_instance))
+    }
+}
+
+/// 把方法绑定到实例上，调用时 this 即为该实例
+fn bind(method: &Value, instance: Rc<RefCell<Value>>) -> Value {
+    let mut bound = method.clone();
+    if let Value::Function { obj_bind, .. } = &mut bound {
+        *obj_bind = Some(instance);
+    }
+    bound
+}
+
+/// Lox 的真假规则：nil 和 false 为假，其余的值都为真
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Nil | Value::Null | Value::Bool(false))
+}
+
+/// 表达式结果的真假，None 视为 nil
+fn is_truthy_option(value: &Option<Rc<RefCell<Value>>>) -> bool {
+    value.as_ref().is_some_and(|value| is_truthy(&value.borrow()))
+}
+
+/// 判断两个值是否相等
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l == r,
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Bool(l), Value::Bool(r)) => l == r,
+        (Value::Nil, Value::Nil) => true,
+        // 列表、类和实例按对象判断是否相等
+        (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+        (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+        (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+        // 函数按定义判断：函数体与 closure 相同，绑定的实例也相同
+        (Value::Function { body: l_body, closure: l_closure, obj_bind: l_bind, .. },
+            Value::Function { body: r_body, closure: r_closure, obj_bind: r_bind, .. }) => {
+            Rc::ptr_eq(l_body, r_body) && Rc::ptr_eq(l_closure, r_closure) && match (l_bind, r_bind) {
+                (Some(l), Some(r)) => values_equal(&l.borrow(), &r.borrow()),
+                (None, None) => true,
+                _ => false,
+            }
         }
+        (Value::Native { func: l, .. }, Value::Native { func: r, .. }) => Rc::ptr_eq(&l.0, &r.0),
+        _ => false,
     }
 }
 
-pub fn traverse_literal(literal: &Literal, depth: usize) -> Value{//获取字面量的值
+pub fn traverse_literal(literal: &Literal) -> Value{//获取字面量的值
     match literal {
-        lox_interpreter::Literal::Number(value) => {
-            Value::Number(value.clone())
+        Literal::Number(value) => {
+            Value::Number(*value)
         }
-        lox_interpreter::Literal::String(value) => {
+        Literal::String(value) => {
             Value::String(value.clone())
         }
-        lox_interpreter::Literal::Bool(value) => {
+        Literal::Bool(value) => {
             Value::Bool(*value)
         }
-        lox_interpreter::Literal::Nil => {
+        Literal::Nil => {
             Value::Nil
         }
     }
-}
\ No newline at end of file
+}
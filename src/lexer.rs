@@ -1,179 +1,301 @@
-use std::str::Chars;
+use std::str::CharIndices;
 use std::iter::Peekable;
-use crate::token::Token;
+use crate::error::LexError;
+use crate::token::{Span, Token, TokenKind};
 
 pub struct Lexer<'a> {
-    input: Peekable<Chars<'a>>,
+    source: &'a str,
+    input: Peekable<CharIndices<'a>>,
+    line: usize,   // 当前行号
+    column: usize, // 当前列号
+    start: Span,   // 当前 token 的起始位置
+    errors: Vec<LexError>, // 扫描过程中遇到的词法错误
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
-        Lexer {
-            input: source.chars().peekable(),
+        let mut lexer = Lexer {
+            source,
+            input: source.char_indices().peekable(),
+            line: 1,
+            column: 1,
+            start: Span::default(),
+            errors: Vec::new(),
+        };
+        // 跳过脚本开头的 shebang 行（#!/usr/bin/env lox）
+        if source.starts_with("#!") {
+            while lexer.peek().is_some_and(|ch| ch != '\n') {
+                lexer.advance();
+            }
         }
+        lexer
+    }
+
+    /// 扫描全部源代码，返回以 Eof 结尾的 token 序列和所有词法错误
+    pub fn scan_tokens(mut self) -> (Vec<Token>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token();
+            let at_end = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if at_end {
+                break;
+            }
+        }
+        (tokens, self.errors)
+    }
+
+    /// 目前为止记录的词法错误
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
     }
 
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
-        
-        match self.input.peek() {
-            Some(&ch) => match ch {
-                // 单字符 token
-                '(' => { self.input.next(); Token::LeftParen },
-                ')' => { self.input.next(); Token::RightParen },
-                '{' => { self.input.next(); Token::LeftBrace },
-                '}' => { self.input.next(); Token::RightBrace },
-                ',' => { self.input.next(); Token::Comma },
-                '.' => { self.input.next(); Token::Dot },
-                '-' => { self.input.next(); Token::Minus },
-                '+' => { self.input.next(); Token::Plus },
-                ';' => { self.input.next(); Token::Semicolon },
-                '*' => { self.input.next(); Token::Star },
-                
-                // 可能双字符的 token
-                '!' => {
-                    self.input.next();
-                    if self.match_char('=') {
-                        Token::BangEqual
-                    } else {
-                        Token::Bang
-                    }
-                },
-                '=' => {
-                    self.input.next();
-                    if self.match_char('=') {
-                        Token::EqualEqual
-                    } else {
-                        Token::Equal
-                    }
-                },
-                '<' => {
-                    self.input.next();
-                    if self.match_char('=') {
-                        Token::LessEqual
-                    } else {
-                        Token::Less
-                    }
-                },
-                '>' => {
-                    self.input.next();
-                    if self.match_char('=') {
-                        Token::GreaterEqual
-                    } else {
-                        Token::Greater
-                    }
-                },
-                '/' => {
-                    self.input.next();
-                    if self.match_char('/') {
-                        // 注释，跳过直到行尾
-                        while let Some(&ch) = self.input.peek() {
-                            if ch == '\n' {
-                                break;
+        // 注释和出错的输入不产生 token，跳过之后继续扫描；用循环而不是递归，大量错误字符也不会耗尽栈空间
+        loop {
+            self.skip_whitespace();
+            self.start = Span {
+                line: self.line,
+                column: self.column,
+                offset: self.offset(),
+                len: 0,
+            };
+
+            let token = match self.peek() {
+                Some(ch) => match ch {
+                    // 单字符 token
+                    '(' => { self.advance(); self.make_token(TokenKind::LeftParen) },
+                    ')' => { self.advance(); self.make_token(TokenKind::RightParen) },
+                    '{' => { self.advance(); self.make_token(TokenKind::LeftBrace) },
+                    '}' => { self.advance(); self.make_token(TokenKind::RightBrace) },
+                    '[' => { self.advance(); self.make_token(TokenKind::LeftBracket) },
+                    ']' => { self.advance(); self.make_token(TokenKind::RightBracket) },
+                    ',' => { self.advance(); self.make_token(TokenKind::Comma) },
+                    '.' => { self.advance(); self.make_token(TokenKind::Dot) },
+                    '-' => { self.advance(); self.make_token(TokenKind::Minus) },
+                    '+' => { self.advance(); self.make_token(TokenKind::Plus) },
+                    ';' => { self.advance(); self.make_token(TokenKind::Semicolon) },
+                    '*' => { self.advance(); self.make_token(TokenKind::Star) },
+
+                    // 可能双字符的 token
+                    '!' => {
+                        self.advance();
+                        if self.match_char('=') {
+                            self.make_token(TokenKind::BangEqual)
+                        } else {
+                            self.make_token(TokenKind::Bang)
+                        }
+                    },
+                    '=' => {
+                        self.advance();
+                        if self.match_char('=') {
+                            self.make_token(TokenKind::EqualEqual)
+                        } else {
+                            self.make_token(TokenKind::Equal)
+                        }
+                    },
+                    '<' => {
+                        self.advance();
+                        if self.match_char('=') {
+                            self.make_token(TokenKind::LessEqual)
+                        } else {
+                            self.make_token(TokenKind::Less)
+                        }
+                    },
+                    '>' => {
+                        self.advance();
+                        if self.match_char('=') {
+                            self.make_token(TokenKind::GreaterEqual)
+                        } else {
+                            self.make_token(TokenKind::Greater)
+                        }
+                    },
+                    '/' => {
+                        self.advance();
+                        if self.match_char('/') {
+                            // 注释，跳过直到行尾
+                            while let Some(ch) = self.peek() {
+                                if ch == '\n' {
+                                    break;
+                                }
+                                self.advance();
                             }
-                            self.input.next();
+                            continue;
+                        } else {
+                            self.make_token(TokenKind::Slash)
                         }
-                        self.next_token()  // 递归调用处理注释后的内容
-                    } else {
-                        Token::Slash
-                    }
+                    },
+
+                    // 字符串字面量
+                    '"' => match self.string() {
+                        Some(token) => token,
+                        None => continue,
+                    },
+
+                    // 数字字面量
+                    '0'..='9' => match self.number() {
+                        Some(token) => token,
+                        None => continue,
+                    },
+
+                    // 标识符或关键字
+                    'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
+
+                    // 无法识别的字符：记录错误，跳过该字符后继续扫描
+                    _ => {
+                        self.advance();
+                        self.report(format!("Unexpected character '{}'.", ch));
+                        continue;
+                    },
                 },
-                
-                // 字符串字面量
-                '"' => self.string(),
-                
-                // 数字字面量
-                '0'..='9' => self.number(),
-                
-                // 标识符或关键字
-                'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
-                
-                _ => panic!("Error at '{}': Unexpected character.", ch),
-            },
-            None => Token::Eof,
+                None => self.make_token(TokenKind::Eof),
+            };
+            return token;
+        }
+    }
+
+    /// 当前读取位置的字节偏移
+    fn offset(&mut self) -> usize {
+        match self.input.peek() {
+            Some(&(offset, _)) => offset,
+            None => self.source.len(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.input.peek().map(|&(_, ch)| ch)
+    }
+
+    /// 消费一个字符，同时维护行号和列号
+    fn advance(&mut self) -> Option<char> {
+        let (_, ch) = self.input.next()?;
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
+        Some(ch)
+    }
+
+    /// 用从 start 到当前位置的源代码构造 token
+    fn make_token(&mut self, kind: TokenKind) -> Token {
+        let end = self.offset();
+        let mut span = self.start;
+        span.len = end - span.offset;
+        Token::new(kind, &self.source[span.offset..end], span)
+    }
+
+    /// 记录一个从 start 到当前位置的词法错误
+    fn report(&mut self, message: impl Into<String>) {
+        let end = self.offset();
+        let mut span = self.start;
+        span.len = end - span.offset;
+        self.errors.push(LexError::new(span, message));
     }
-    
+
     fn skip_whitespace(&mut self) {
-        while let Some(&ch) = self.input.peek() {
+        while let Some(ch) = self.peek() {
             if ch.is_whitespace() {
-                self.input.next();
+                self.advance();
             } else {
                 break;
             }
         }
     }
-    
+
     fn match_char(&mut self, expected: char) -> bool {
-        if let Some(&ch) = self.input.peek() {
-            if ch == expected {
-                self.input.next();
-                return true;
-            }
+        if self.peek() == Some(expected) {
+            self.advance();
+            return true;
         }
         false
     }
-    
-    fn string(&mut self) -> Token {
-        self.input.next(); // 跳过开始的引号
-        
+
+    /// 字符串字面量，没有结束的引号时记录错误并返回 None
+    fn string(&mut self) -> Option<Token> {
+        self.advance(); // 跳过开始的引号
+
         let mut s = String::new();
-        while let Some(&ch) = self.input.peek() {
+        while let Some(ch) = self.peek() {
             if ch == '"' {
-                self.input.next(); // 跳过结束的引号
-                return Token::String(s);
+                self.advance(); // 跳过结束的引号
+                return Some(self.make_token(TokenKind::String(s)));
             }
             s.push(ch);
-            self.input.next();
+            self.advance();
         }
-        
-        panic!("Unterminated string");
-    }
-    
-    fn number(&mut self) -> Token {
-        let mut num = String::new();
-        while let Some(&ch) = self.input.peek() {
-            if ch.is_ascii_digit() || ch == '.' {
-                num.push(ch);
-                self.input.next();
-            } else {
-                break;
+
+        // 字符串一直延续到文件末尾
+        self.report("Unterminated string.");
+        None
+    }
+
+    /// 数字字面量，无法解析时记录错误并返回 None
+    fn number(&mut self) -> Option<Token> {
+        self.digits();
+        // 小数点后必须紧跟数字，否则 '.' 留给后面作为单独的 token
+        if self.peek() == Some('.') && self.peek_next().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.advance();
+            self.digits();
+        }
+
+        let end = self.offset();
+        match self.source[self.start.offset..end].parse() {
+            Ok(value) => Some(self.make_token(TokenKind::Number(value))),
+            Err(_) => {
+                self.report("Invalid number literal.");
+                None
             }
         }
-        
-        Token::Number(num.parse().unwrap())
     }
-    
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.advance();
+        }
+    }
+
+    /// 向前多看一个字符
+    fn peek_next(&self) -> Option<char> {
+        let mut rest = self.input.clone();
+        rest.next();
+        rest.next().map(|(_, ch)| ch)
+    }
+
     fn identifier(&mut self) -> Token {
         let mut ident = String::new();
-        while let Some(&ch) = self.input.peek() {
+        while let Some(ch) = self.peek() {
             if ch.is_ascii_alphanumeric() || ch == '_' {
                 ident.push(ch);
-                self.input.next();
+                self.advance();
             } else {
                 break;
             }
         }
-        
+
         // 检查是否是关键字
-        match ident.as_str() {
-            "and" => Token::And,
-            "class" => Token::Class,
-            "else" => Token::Else,
-            "false" => Token::False,
-            "fun" => Token::Fun,
-            "for" => Token::For,
-            "if" => Token::If,
-            "nil" => Token::Nil,
-            "or" => Token::Or,
-            "print" => Token::Print,
-            "return" => Token::Return,
-            "super" => Token::Super,
-            "this" => Token::This,
-            "true" => Token::True,
-            "var" => Token::Var,
-            "while" => Token::While,
-            _ => Token::Identifier(ident),
-        }
+        let kind = match ident.as_str() {
+            "and" => TokenKind::And,
+            "break" => TokenKind::Break,
+            "class" => TokenKind::Class,
+            "continue" => TokenKind::Continue,
+            "else" => TokenKind::Else,
+            "false" => TokenKind::False,
+            "fun" => TokenKind::Fun,
+            "for" => TokenKind::For,
+            "if" => TokenKind::If,
+            "nil" => TokenKind::Nil,
+            "or" => TokenKind::Or,
+            "print" => TokenKind::Print,
+            "return" => TokenKind::Return,
+            "super" => TokenKind::Super,
+            "this" => TokenKind::This,
+            "true" => TokenKind::True,
+            "var" => TokenKind::Var,
+            "while" => TokenKind::While,
+            _ => TokenKind::Identifier(ident),
+        };
+        self.make_token(kind)
     }
 }
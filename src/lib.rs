@@ -2,9 +2,16 @@ pub mod token;
 pub mod lexer;
 pub mod ast;
 pub mod parser;
+pub mod resolver;
+pub mod environment;
+pub mod error;
+pub mod intepreter;
+pub mod strings;
 
 
 pub use token::Token;
 pub use lexer::Lexer;
 pub use ast::{Expr, Literal, Stmt};
-pub use parser::Parser;
\ No newline at end of file
+pub use parser::Parser;
+pub use error::{Expected, LexError, LoxError, ParseError, ResolveError, RuntimeError, RuntimeErrorKind};
+pub use intepreter::{Interpreter, NativeFn, Value};
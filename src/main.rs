@@ -1,12 +1,13 @@
-use intepreter::{Framelist, Value};
-use lox_interpreter::{lexer::Lexer, parser::Parser, Expr, Literal, Stmt};
+use lox_interpreter::{Interpreter, Lexer, LoxError, Parser};
+use rustyline::{error::ReadlineError, DefaultEditor};
 use std::{
-    cell::RefCell, collections::HashMap, fs, io::{self, Write}, path::Path, rc::Rc
+    io::{self, Read}, path::{Path, PathBuf}, process
 };
-use lox_interpreter::token::Token;
-use std::sync::atomic::{AtomicBool, Ordering};
 
-mod intepreter;
+/// 命令行用法错误（EX_USAGE）
+const EXIT_USAGE: i32 = 64;
+/// 读取输入失败（EX_IOERR）
+const EXIT_IO: i32 = 74;
 
 fn main() {
     // println!("Lox Interpreter (Rust)");
@@ -15,11 +16,23 @@ fn main() {
     // println!("  File mode: provide a .lox file path\n");
 
     let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        let file_path = &args[1];
-        process_file(file_path);
-    } else {
-        interactive_mode();
+    let result = match &args[1..] {
+        [] => {
+            interactive_mode();
+            Ok(())
+        }
+        // 从标准输入读取整个脚本
+        [dash] if dash == "-" => process_stdin(),
+        // 直接执行命令行给出的代码
+        [flag, code] if flag == "-e" => run_source(code),
+        [file_path] if !file_path.starts_with('-') => process_file(file_path),
+        _ => {
+            eprintln!("Usage: {} [script.lox | - | -e <code>]", args[0]);
+            Err(EXIT_USAGE)
+        }
+    };
+    if let Err(code) = result {
+        process::exit(code);
     }
 }
 
@@ -27,73 +40,184 @@ fn interactive_mode() {
     // println!("Entering interactive mode...");
     // println!("Type Lox expressions or 'exit' to quit\n");
 
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO);
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // 第一次运行时历史文件还不存在
+        let _ = editor.load_history(path);
+    }
+
+    // 整个会话共用一个解释器，前面定义的变量、函数和类在后续输入中仍然可用
+    let mut interpreter = Interpreter::new();
+    // 尚未执行的多行输入
+    let mut buffer = String::new();
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+        let input = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C 丢弃当前输入
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            // Ctrl-D 退出，尚未写完的输入强制执行，报告其中的错误
+            Err(ReadlineError::Eof) => {
+                if !buffer.is_empty() {
+                    process_input(&mut interpreter, &buffer, true);
+                }
+                break;
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                break;
+            }
+        };
+        if !input.trim().is_empty() {
+            let _ = editor.add_history_entry(input.as_str());
+        }
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        if buffer.is_empty() {
+            let command = input.trim();
+            if command == "exit" {
+                break;
+            }
+            if let Some(command) = command.strip_prefix(':') {
+                if !meta_command(&mut interpreter, command) {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        // 续行时输入空行表示强制执行，直接报告错误
+        let force = !buffer.is_empty() && input.trim().is_empty();
+        buffer.push_str(&input);
+        buffer.push('\n');
+        if process_input(&mut interpreter, &buffer, force) {
+            buffer.clear();
+        }
+    }
 
-        if input.trim() == "exit" {
-            break;
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("Error saving history: {}", e);
         }
+    }
+}
+
+/// 历史记录文件 ~/.lox_history
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".lox_history"))
+}
 
-        process_input(&input);
+/// 执行以 ':' 开头的元命令，返回 false 表示退出交互模式
+fn meta_command(interpreter: &mut Interpreter, command: &str) -> bool {
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+    };
+    match name {
+        "quit" | "q" => return false,
+        "load" => {
+            if arg.is_empty() {
+                eprintln!("Usage: :load <file.lox>");
+            } else if let Err(e) = interpreter.run_file(arg) {
+                report(&e);
+            }
+        }
+        "env" => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, value);
+            }
+        }
+        "ast" => {
+            let (tokens, errors) = Lexer::new(arg).scan_tokens();
+            if !errors.is_empty() {
+                report(&LoxError::Lex(errors));
+            } else {
+                match Parser::new(&tokens).parse_expression() {
+                    Ok(expr) => println!("{}", expr),
+                    Err(errors) => {
+                        report(&LoxError::Parse(errors));
+                    }
+                }
+            }
+        }
+        "tokens" => {
+            let (tokens, errors) = Lexer::new(arg).scan_tokens();
+            for token in &tokens {
+                println!("{} {:?} '{}'", token.span, token.kind, token.lexeme());
+            }
+            if !errors.is_empty() {
+                report(&LoxError::Lex(errors));
+            }
+        }
+        "reset" => *interpreter = Interpreter::new(),
+        _ => eprintln!("Unknown command ':{}'. Commands: :load :env :ast :tokens :reset :quit", name),
     }
+    true
 }
 
-fn process_file(file_path: &str) {
+/// 执行脚本文件，失败时返回进程退出码
+fn process_file(file_path: &str) -> Result<(), i32> {
     let path = Path::new(file_path);
     if !path.exists() {
         eprintln!("Error: File not found - {}", file_path);
-        return;
+        return Err(EXIT_IO);
     }
 
-    if path.extension().map_or(true, |ext| ext != "in") {
+    // .lox 为标准扩展名，.in 是测试用例使用的扩展名，没有扩展名的一般是 shebang 脚本
+    if path.extension().is_some_and(|ext| ext != "lox" && ext != "in") {
         eprintln!("Error: Expected .lox file");
-        return;
+        return Err(EXIT_USAGE);
     }
 
-    match fs::read_to_string(path) {
-        Ok(source) => {
-            // println!("Parsing file: {}\n", file_path);
-            process_input(&source);
-        }
-        Err(e) => eprintln!("Error reading file: {}", e),
+    let mut interpreter = Interpreter::new();
+    interpreter.run_file(path).map_err(|e| report(&e))
+}
+
+fn process_stdin() -> Result<(), i32> {
+    let mut source = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut source) {
+        eprintln!("Error reading stdin: {}", e);
+        return Err(EXIT_IO);
     }
+    run_source(&source)
+}
+
+fn run_source(source: &str) -> Result<(), i32> {
+    let mut interpreter = Interpreter::new();
+    interpreter.run_source(source).map_err(|e| report(&e))
 }
 
-fn process_input(input: &str) {
+/// 执行交互模式的输入，返回 false 表示输入还不完整，需要继续读取下一行
+fn process_input(interpreter: &mut Interpreter, input: &str, force: bool) -> bool {
     if input.trim().is_empty() {
-        return;
+        return true;
     }
 
-    // 词法分析
-    let mut lexer = Lexer::new(input);
-    let mut tokens = Vec::new();
-    
-    loop {
-        let token = lexer.next_token();
-        if token == Token::Eof {
-            break;
+    match interpreter.run_repl_input(input) {
+        Ok(()) => true,
+        // 括号没有闭合、语句没有写完时，语法分析会在输入末尾报错
+        Err(LoxError::Parse(errors)) if !force && errors.last().is_some_and(|e| e.at_end()) => false,
+        Err(e) => {
+            report(&e);
+            true
         }
-        tokens.push(token);
     }
+}
 
-    /*println!("\nTokens:");
-    for token in &tokens {
-        println!("  {:?}", token);
-    }*/
-
-    // 语法分析
-    let mut parser = Parser::new(&tokens);
-    //println!("\nAST:");
-    let statements = parser.parse();  // 直接获取Vec<Stmt>
-    
-    let mut map: HashMap<(String,String), Option<Rc<RefCell<Value>>>> = HashMap::new();
-    let mut env: Framelist = Framelist{
-        next: None,
-        frame: "__global__".to_string(),
-    };
-    intepreter::traverse_statements(&statements,0,&mut map,env,None,None);
+/// 把错误信息输出到 stderr，返回对应的退出码
+fn report(error: &LoxError) -> i32 {
+    match error {
+        LoxError::Runtime(e) => eprintln!("{}", e.report()),
+        e => eprintln!("{}", e),
+    }
+    error.exit_code()
 }
@@ -1,17 +1,35 @@
+use std::cell::Cell;
+
 use crate::{
     ast::{Expr, Literal, Stmt},
-    token::Token,
+    error::{Expected, ParseError},
+    token::{Span, Token, TokenKind},
+};
+
+/// 语句、代码块和表达式最多嵌套的层数
+const MAX_NESTING: usize = 256;
+/// 剩余栈空间少于 STACK_RED_ZONE 时，为下一层嵌套分配 STACK_SEGMENT_SIZE 大小的新栈段
+const STACK_RED_ZONE: usize = 64 * 1024;
+const STACK_SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// token 序列没有以 Eof 结尾时使用的哨兵
+static EOF: Token = Token {
+    kind: TokenKind::Eof,
+    lexeme: String::new(),
+    span: Span { line: 0, column: 0, offset: 0, len: 0 },
 };
 
 pub struct Parser<'a> {
     tokens: &'a [Token],
     current: usize,
-    function_depth: usize, // 跟踪函数嵌套深度
+    errors: Vec<ParseError>, // 已经报告的语法错误
+    depth: usize,            // 当前嵌套的语句、代码块和表达式层数
+    too_deep: bool,          // 嵌套过深，已经放弃解析剩余的输入
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, current: 0, function_depth: 0, }
+        Self { tokens, current: 0, errors: Vec::new(), depth: 0, too_deep: false }
     }
 
     // --------------------------------------------
@@ -28,61 +46,32 @@ impl<'a> Parser<'a> {
 
 
     /// 检查当前token是否匹配给定类型
-    fn check(&self, token_type: &Token) -> bool {
+    fn check(&self, token_type: &TokenKind) -> bool {
         if self.is_at_end() {
             false
         } else {
-            std::mem::discriminant(self.peek()) == std::mem::discriminant(token_type)
+            std::mem::discriminant(&self.peek().kind) == std::mem::discriminant(token_type)
         }
     }
 
-    /// 如果匹配则消费token
-    fn consume(&mut self, expected: &Token, message: &str) -> Result<&Token, String> {
+    /// 如果匹配则消费token，否则报告期望的token
+    fn consume(&mut self, expected: &TokenKind, message: &str) -> Result<&Token, ParseError> {
         if self.check(expected) {
             Ok(self.advance())
         } else {
-            let show_details_messages = &[
-                "Expect ';' after value.",
-                "Expect ')' after parameters.",
-                "Expect variable name.",
-                "Unexpected character.",
-                "Expect '}' after block.",
-                "Can't return from top-level code.",
-                "Can't use 'break' outside of a loop.",
-                "Can't use 'continue' outside of a loop.",
-                "Already a variable with this name in this scope.",
-                "Superclass must be a class.",
-                "Can't use 'super' in a class with no superclass.",
-                "Can't use 'this' outside of a class."
-            ];
-            let should_show_details = show_details_messages.iter()
-            .any(|&m| message.starts_with(m));
-        
-            if should_show_details {
-                Err(self.error(self.peek(), message))
-            } else {
-                // 对于不需要详细信息的错误，只返回错误类型部分
-                // let error_type = message.split(':').next().unwrap_or(message).trim();
-                // Err(error_type.to_string())
-                Err("".to_string())
-            }
-            //Err(self.error(self.peek(), message))
+            Err(ParseError::new(self.peek(), Some(Expected::Token(expected.clone())), message))
         }
     }
 
     /// 查看当前token
     fn peek(&self) -> &Token {
-        if self.is_at_end() {
-            &Token::Eof
-        } else {
-            &self.tokens[self.current]
-        }
+        self.tokens.get(self.current).unwrap_or(&EOF)
     }
 
     /// 查看前一个token
     fn previous(&self) -> &Token {
         if self.current == 0 {
-            &Token::Eof
+            &EOF
         } else {
             &self.tokens[self.current - 1]
         }
@@ -90,7 +79,7 @@ impl<'a> Parser<'a> {
 
     /// 是否到达末尾
     fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len()
+        self.peek().kind == TokenKind::Eof
     }
 
 
@@ -98,61 +87,91 @@ impl<'a> Parser<'a> {
     // 解析入口点
     // --------------------------------------------
 
-    pub fn parse(&mut self) -> Vec<Stmt> {
+    /// 解析全部语句；出错时跳过出错的语句继续解析，最后一次返回所有语法错误
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            match self.declaration() {
-                Ok(stmt) => statements.push(stmt),
-                Err(e) => {
-                    eprintln!("{}", e);
-                    self.synchronize();
-                }
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// 把全部输入解析为单个表达式
+    pub fn parse_expression(&mut self) -> Result<Expr, Vec<ParseError>> {
+        let expr = self.expression().map_err(|e| vec![e])?;
+        if !self.is_at_end() {
+            return Err(vec![self.error(self.peek(), "Expect end of expression.")]);
+        }
+        Ok(expr)
+    }
+
+    /// 交互模式的解析入口：输入恰好是一个表达式时当作 print 语句，否则按普通程序解析
+    pub fn parse_repl(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        if let Ok(expr) = self.expression() {
+            if self.is_at_end() {
+                return Ok(vec![Stmt::Print(expr)]);
             }
         }
-        statements
+        self.current = 0;
+        self.errors.clear();
+        self.too_deep = false;
+        self.parse()
     }
 
     // --------------------------------------------
     // 声明解析框架
     // --------------------------------------------
 
-    fn declaration(&mut self) -> Result<Stmt, String> {
-        let result = if self.check(&Token::Class) {
+    /// 出错时记录错误并同步到下一条语句，返回 None
+    fn declaration(&mut self) -> Option<Stmt> {
+        let result = if self.check(&TokenKind::Class) {
             self.class_declaration()
-        } else if self.check(&Token::Fun) {
+        } else if self.check(&TokenKind::Fun) {
             self.function_declaration()
-        } else if self.check(&Token::Var) {
+        } else if self.check(&TokenKind::Var) {
             self.var_declaration()
         } else {
             self.statement()
         };
         
-        if result.is_err() {
-            self.synchronize();
+        match result {
+            Ok(stmt) => Some(stmt),
+            // 嵌套过深的错误已经记录，外层不再报告缺少 '}' 等后续错误
+            Err(_) if self.too_deep => None,
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
         }
-        result
     }
 
-    fn class_declaration(&mut self) -> Result<Stmt, String> {
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
         let _class_token = self.advance().clone();
-        let name = self.consume_identifier("Expect class name")?;
+        let name = self.consume_identifier("Expect class name.")?;
         
-        let superclass = if self.check(&Token::Less) {
+        let superclass = if self.check(&TokenKind::Less) {
             self.advance();
-            let super_name = self.consume_identifier("Expect superclass name")?;
-            Some(Expr::Variable(super_name))
+            let super_name = self.consume_identifier("Expect superclass name.")?;
+            Some(Expr::variable(super_name))
         } else {
             None
         };
         
-        self.consume(&Token::LeftBrace, "Expect '{' before class body")?;
+        self.consume(&TokenKind::LeftBrace, "Expect '{' before class body.")?;
         
         let mut methods = Vec::new();
-        while !self.check(&Token::RightBrace) && !self.is_at_end() {
+        while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
             methods.push(self.method()?);
         }
         
-        self.consume(&Token::RightBrace, "Expect '}' after class body")?;
+        self.consume(&TokenKind::RightBrace, "Expect '}' after class body.")?;
         Ok(Stmt::Class {
             name,
             superclass,
@@ -161,34 +180,31 @@ impl<'a> Parser<'a> {
     }
     
     // 新增方法：专门解析类方法
-    fn method(&mut self) -> Result<Stmt, String> {
-        let name = self.consume_identifier("Expect method name")?;
+    fn method(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume_identifier("Expect method name.")?;
         
-        self.consume(&Token::LeftParen, "Expect '(' after method name")?;
+        self.consume(&TokenKind::LeftParen, "Expect '(' after method name.")?;
         
         let mut params = Vec::new();
-        if !self.check(&Token::RightParen) {
+        if !self.check(&TokenKind::RightParen) {
             loop {
                 if params.len() >= 255 {
-                    return Err(self.error(self.peek(), "Can't have more than 255 parameters"));
+                    return Err(self.error(self.peek(), "Can't have more than 255 parameters."));
                 }
                 
-                params.push(self.consume_identifier("Expect parameter name")?);
+                params.push(self.consume_identifier("Expect parameter name.")?);
                 
-                if !self.check(&Token::Comma) {
+                if !self.check(&TokenKind::Comma) {
                     break;
                 }
                 self.advance();
             }
         }
         
-        self.consume(&Token::RightParen, "Expect ')' after parameters.")?;
+        self.consume(&TokenKind::RightParen, "Expect ')' after parameters.")?;
         
-        let body = if self.check(&Token::LeftBrace) {
-            self.function_depth += 1;
-            let block_body = self.block()?;
-            self.function_depth -= 1;
-            block_body
+        let body = if self.check(&TokenKind::LeftBrace) {
+            self.block()?
         } else {
             // 如果方法体不是块语句，则创建只包含一个语句的块
             let stmt = self.statement()?;
@@ -197,105 +213,71 @@ impl<'a> Parser<'a> {
         
         Ok(Stmt::Function {
             name,
-            params,
-            body,
+            params: params.into(),
+            body: body.into(),
         })
     }
     
 
-    fn function_declaration(&mut self) -> Result<Stmt, String> {
+    fn function_declaration(&mut self) -> Result<Stmt, ParseError> {
         let _fun_token = self.advance().clone();  // 消费'fun'
-        let name = self.consume_identifier("Expect function name")?;
+        let name = self.consume_identifier("Expect function name.")?;
         
-        self.consume(&Token::LeftParen, "Expect '(' after function name")?;
+        self.consume(&TokenKind::LeftParen, "Expect '(' after function name.")?;
         
         let mut params = Vec::new();
-        if !self.check(&Token::RightParen) {
+        if !self.check(&TokenKind::RightParen) {
             loop {
                 if params.len() >= 255 {
-                    return Err(self.error(self.peek(), "Can't have more than 255 parameters"));
+                    return Err(self.error(self.peek(), "Can't have more than 255 parameters."));
                 }
                 
-                let param = self.consume_identifier("Expect parameter name")?;
+                let param = self.consume_identifier("Expect parameter name.")?;
                 params.push(param);
                 
-                if !self.check(&Token::Comma) {
+                if !self.check(&TokenKind::Comma) {
                     break;
                 }
                 self.advance();  // 消费逗号
             }
         }
         
-        self.consume(&Token::RightParen, "Expect ')' after parameters.")?;
+        self.consume(&TokenKind::RightParen, "Expect ')' after parameters.")?;
         
-        let body = if self.check(&Token::LeftBrace) {
-            self.function_depth += 1;
-            let block_body = self.block()?;
-            self.function_depth -= 1;
-            block_body
+        let body = if self.check(&TokenKind::LeftBrace) {
+            self.block()?
         } else {
-            return Err(self.error(self.peek(), "Expect '{' before function body"));
+            return Err(ParseError::new(self.peek(), Some(Expected::Token(TokenKind::LeftBrace)), "Expect '{' before function body."));
         };
         
         Ok(Stmt::Function {
             name,
-            params,
-            body,
+            params: params.into(),
+            body: body.into(),
         })
     }
     
     // 添加辅助方法用于消费标识符
-    fn consume_identifier(&mut self, message: &str) -> Result<Token, String> {
-        if let Token::Identifier(_) = self.peek() {
+    fn consume_identifier(&mut self, message: &str) -> Result<Token, ParseError> {
+        if let TokenKind::Identifier(_) = self.peek().kind {
             Ok(self.advance().clone())
         } else {
-            let show_details_messages = &[
-                "Expect ';' after value.",
-                "Expect ')' after parameters.",
-                "Expect variable name.",
-                "Unexpected character.",
-                "Expect '}' after block.",
-                "Can't return from top-level code.",
-                "Can't use 'break' outside of a loop.",
-                "Can't use 'continue' outside of a loop.",
-                "Already a variable with this name in this scope.",
-                "Superclass must be a class.",
-                "Can't use 'super' in a class with no superclass.",
-                "Can't use 'this' outside of a class."
-            ];
-            let should_show_details = show_details_messages.iter()
-            .any(|&m| message.starts_with(m));
-        
-            if should_show_details {
-                Err(self.error(self.peek(), message))
-            }
-            else {
-                // let error_type = message.split(':').next().unwrap_or(message).trim();
-                // Err(error_type.to_string())
-                Err("".to_string())
-            }
-            //Err(self.error(self.peek(), message))
+            Err(ParseError::new(self.peek(), Some(Expected::Identifier), message))
         }
     }
-    
 
-
-    fn var_declaration(&mut self) -> Result<Stmt, String> {
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
         let _var_token = self.advance().clone();
-        let name = if let Token::Identifier(name) = self.advance().clone() {
-            Token::Identifier(name)
-        } else {
-            return Err(self.error(self.previous(), "Expect variable name."));
-        };
+        let name = self.consume_identifier("Expect variable name.")?;
         
-        let initializer = if self.check(&Token::Equal) {
+        let initializer = if self.check(&TokenKind::Equal) {
             self.advance();
             Some(self.expression()?)
         } else {
             None
         };
         
-        self.consume(&Token::Semicolon, "Expect ';' after value.")?;
+        self.consume(&TokenKind::Semicolon, "Expect ';' after variable declaration.")?;
         Ok(Stmt::Var { name, initializer })
     }
 
@@ -303,67 +285,71 @@ impl<'a> Parser<'a> {
     // 语句解析框架
     // --------------------------------------------
 
-    fn statement(&mut self) -> Result<Stmt, String> {
-        match self.peek() {
-            Token::Print => self.print_statement(),
-            Token::LeftBrace => Ok(Stmt::Block(self.block()?)),
-            Token::If => self.if_statement(),
-            Token::While => self.while_statement(),
-            Token::For => self.for_statement(),
-            Token::Return => self.return_statement(),
-            _ => self.expr_statement(),
-        }
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.nested(|parser| match &parser.peek().kind {
+            TokenKind::Print => parser.print_statement(),
+            TokenKind::LeftBrace => Ok(Stmt::Block(parser.block()?)),
+            TokenKind::If => parser.if_statement(),
+            TokenKind::While => parser.while_statement(),
+            TokenKind::For => parser.for_statement(),
+            TokenKind::Return => parser.return_statement(),
+            TokenKind::Break => {
+                let keyword = parser.advance().clone();
+                parser.consume(&TokenKind::Semicolon, "Expect ';' after 'break'.")?;
+                Ok(Stmt::Break(keyword))
+            }
+            TokenKind::Continue => {
+                let keyword = parser.advance().clone();
+                parser.consume(&TokenKind::Semicolon, "Expect ';' after 'continue'.")?;
+                Ok(Stmt::Continue(keyword))
+            }
+            _ => parser.expr_statement(),
+        })
     }
 
-    fn expr_statement(&mut self) -> Result<Stmt, String> {
+    fn expr_statement(&mut self) -> Result<Stmt, ParseError> {
         let expr = self.expression()?;
-        self.consume(&Token::Semicolon, "Expect ';' after value.")?;
+        self.consume(&TokenKind::Semicolon, "Expect ';' after expression.")?;
         Ok(Stmt::Expr(expr))
     }
 
-    fn for_statement(&mut self) -> Result<Stmt, String> {
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
         let for_token = self.advance().clone();
-        self.consume(&Token::LeftParen, "Expect '(' after 'for'")?;
+        self.consume(&TokenKind::LeftParen, "Expect '(' after 'for'.")?;
         
         // 初始化部分
-        let initializer = if self.check(&Token::Semicolon) {
+        let initializer = if self.check(&TokenKind::Semicolon) {
             self.advance();
             None
-        } else if self.check(&Token::Var) {
+        } else if self.check(&TokenKind::Var) {
             Some(Box::new(self.var_declaration()?))
         } else {
             Some(Box::new(self.expr_statement()?))
         };
         
         // 条件部分
-        let condition = if !self.check(&Token::Semicolon) {
+        let condition = if !self.check(&TokenKind::Semicolon) {
             Some(self.expression()?)
         } else {
             None
         };
-        self.consume(&Token::Semicolon, "Expect ';' after value.")?;
+        self.consume(&TokenKind::Semicolon, "Expect ';' after loop condition.")?;
         
         // 增量部分
-        let increment = if !self.check(&Token::RightParen) {
+        let increment = if !self.check(&TokenKind::RightParen) {
             Some(self.expression()?)
         } else {
             None
         };
-        self.consume(&Token::RightParen, "Expect ')' after for clauses")?;
+        self.consume(&TokenKind::RightParen, "Expect ')' after for clauses.")?;
         
-        let mut body = self.statement()?;
-        
-        // 将for循环转换为while循环
-        if let Some(inc) = increment {
-            body = Stmt::Block(vec![
-                body,
-                Stmt::Expr(inc),
-            ]);
-        }
+        let body = self.statement()?;
         
-        body = Stmt::While {
-            condition: condition.unwrap_or(Expr::Literal(Literal::Bool(true))),
+        // 将for循环转换为while循环，增量表达式单独保存，continue 之后仍会执行
+        let mut body = Stmt::While {
+            condition: condition.unwrap_or(Expr::Literal { value: Literal::Bool(true), span: for_token.span }),
             body: Box::new(body),
+            increment,
         };
         
         if let Some(init) = initializer {
@@ -376,14 +362,14 @@ impl<'a> Parser<'a> {
         Ok(body)
     }
 
-    fn if_statement(&mut self) -> Result<Stmt, String> {
-        let if_token = self.advance().clone();
-        self.consume(&Token::LeftParen, "Expect '(' after 'if'")?;
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let _if_token = self.advance().clone();
+        self.consume(&TokenKind::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
-        self.consume(&Token::RightParen, "Expect ')' after if condition")?;
+        self.consume(&TokenKind::RightParen, "Expect ')' after if condition.")?;
         
         let then_branch = Box::new(self.statement()?);
-        let else_branch = if self.check(&Token::Else) {
+        let else_branch = if self.check(&TokenKind::Else) {
             self.advance();
             Some(Box::new(self.statement()?))
         } else {
@@ -397,48 +383,48 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, String> {
-        let print_token = self.advance().clone();
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let _print_token = self.advance().clone();
         let expr = self.expression()?;
-        self.consume(&Token::Semicolon, "Expect ';' after value.")?;
+        self.consume(&TokenKind::Semicolon, "Expect ';' after value.")?;
         Ok(Stmt::Print(expr))
     }
 
-    fn return_statement(&mut self) -> Result<Stmt, String> {
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
         let keyword = self.advance().clone();
-        // 检查是否在函数内
-        if self.function_depth == 0 {
-            return Err("Error: Can't return from top-level code.".to_string());
-        }
-        let value = if !self.check(&Token::Semicolon) {
+        let value = if !self.check(&TokenKind::Semicolon) {
             Some(self.expression()?)
         } else {
             None
         };
-        self.consume(&Token::Semicolon, "Expect ';' after value.")?;
+        self.consume(&TokenKind::Semicolon, "Expect ';' after return value.")?;
         Ok(Stmt::Return { keyword, value })
     }
 
-    fn while_statement(&mut self) -> Result<Stmt, String> {
-        let while_token = self.advance().clone();
-        self.consume(&Token::LeftParen, "Expect '(' after 'while'")?;
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let _while_token = self.advance().clone();
+        self.consume(&TokenKind::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
-        self.consume(&Token::RightParen, "Expect ')' after condition")?;
+        self.consume(&TokenKind::RightParen, "Expect ')' after condition.")?;
         let body = Box::new(self.statement()?);
         
-        Ok(Stmt::While { condition, body })
+        Ok(Stmt::While { condition, body, increment: None })
     }
 
-    fn block(&mut self) -> Result<Vec<Stmt>, String> {
-        self.consume(&Token::LeftBrace, "Expect '{' before block")?;
-        
-        let mut statements = Vec::new();
-        while !self.check(&Token::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
-        }
-        
-        self.consume(&Token::RightBrace, "Expect '}' after block.")?;
-        Ok(statements)
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.nested(|parser| {
+            parser.consume(&TokenKind::LeftBrace, "Expect '{' before block.")?;
+
+            let mut statements = Vec::new();
+            while !parser.check(&TokenKind::RightBrace) && !parser.is_at_end() {
+                if let Some(stmt) = parser.declaration() {
+                    statements.push(stmt);
+                }
+            }
+
+            parser.consume(&TokenKind::RightBrace, "Expect '}' after block.")?;
+            Ok(statements)
+        })
     }
 
     // --------------------------------------------
@@ -446,29 +432,29 @@ impl<'a> Parser<'a> {
     // --------------------------------------------
 
     /// expression → assignment
-    pub fn expression(&mut self) -> Result<Expr, String> {
-        self.assignment()
+    pub fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.nested(Self::assignment)
     }
 
     /// assignment → ( call "." )? IDENTIFIER "=" assignment
     ///            | logic_or
-    fn assignment(&mut self) -> Result<Expr, String> {
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
         let expr = self.logic_or()?;
         
-        if self.check(&Token::Equal) {
+        if self.check(&TokenKind::Equal) {
             let equals = self.advance().clone();
-            let value = self.assignment()?; // 递归解析右值
+            let value = self.nested(Self::assignment)?; // 递归解析右值
             
             // 处理普通变量赋值（a = 3）
-            if let Expr::Variable(name) = expr {
-                return Ok(Expr::Assign { name, value: Box::new(value) });
+            if let Expr::Variable { name, .. } = expr {
+                return Ok(Expr::Assign { name, value: Box::new(value), depth: Cell::new(None) });
             }
             // 处理属性赋值（a.b = 3）
             else if let Expr::Get { object, name } = expr {
                 return Ok(Expr::Set { object, name, value: Box::new(value) });
             }
             
-            return Err(self.error(&equals, "Invalid assignment target"));
+            return Err(self.error(&equals, "Invalid assignment target."));
         }
         
         Ok(expr)
@@ -476,10 +462,10 @@ impl<'a> Parser<'a> {
     
 
     /// logic_or → logic_and ( "or" logic_and )*
-    fn logic_or(&mut self) -> Result<Expr, String> {
+    fn logic_or(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.logic_and()?;
         
-        while self.check(&Token::Or) {
+        while self.check(&TokenKind::Or) {
             let operator = self.advance().clone();
             let right = self.logic_and()?;
             expr = Expr::Logical {
@@ -493,10 +479,10 @@ impl<'a> Parser<'a> {
     }
 
     /// logic_and → equality ( "and" equality )*
-    fn logic_and(&mut self) -> Result<Expr, String> {
+    fn logic_and(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.equality()?;
         
-        while self.check(&Token::And) {
+        while self.check(&TokenKind::And) {
             let operator = self.advance().clone();
             let right = self.equality()?;
             expr = Expr::Logical {
@@ -510,10 +496,10 @@ impl<'a> Parser<'a> {
     }
 
     /// equality → comparison ( ("!=" | "==") comparison )*
-    fn equality(&mut self) -> Result<Expr, String> {
+    fn equality(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.comparison()?;
         
-        while matches!(self.peek(), Token::BangEqual | Token::EqualEqual) {
+        while matches!(self.peek().kind, TokenKind::BangEqual | TokenKind::EqualEqual) {
             let operator = self.advance().clone();
             let right = self.comparison()?;
             expr = Expr::Binary {
@@ -527,12 +513,12 @@ impl<'a> Parser<'a> {
     }
 
     /// comparison → term ( (">" | ">=" | "<" | "<=" ) term )*
-    fn comparison(&mut self) -> Result<Expr, String> {
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.term()?;
         
         while matches!(
-            self.peek(),
-            Token::Greater | Token::GreaterEqual | Token::Less | Token::LessEqual
+            self.peek().kind,
+            TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual
         ) {
             let operator = self.advance().clone();
             let right = self.term()?;
@@ -547,10 +533,10 @@ impl<'a> Parser<'a> {
     }
 
     /// term → factor ( ("+" | "-") factor )*
-    fn term(&mut self) -> Result<Expr, String> {
+    fn term(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.factor()?;
         
-        while matches!(self.peek(), Token::Plus | Token::Minus) {
+        while matches!(self.peek().kind, TokenKind::Plus | TokenKind::Minus) {
             let operator = self.advance().clone();
             let right = self.factor()?;
             expr = Expr::Binary {
@@ -564,10 +550,10 @@ impl<'a> Parser<'a> {
     }
 
     /// factor → unary ( ("/" | "*") unary )*
-    fn factor(&mut self) -> Result<Expr, String> {
+    fn factor(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.unary()?;
         
-        while matches!(self.peek(), Token::Slash | Token::Star) {
+        while matches!(self.peek().kind, TokenKind::Slash | TokenKind::Star) {
             let operator = self.advance().clone();
             let right = self.unary()?;
             expr = Expr::Binary {
@@ -581,10 +567,10 @@ impl<'a> Parser<'a> {
     }
 
     /// unary → ("!" | "-") unary | call
-    fn unary(&mut self) -> Result<Expr, String> {
-        if matches!(self.peek(), Token::Bang | Token::Minus) {
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if matches!(self.peek().kind, TokenKind::Bang | TokenKind::Minus) {
             let operator = self.advance().clone();
-            let right = self.unary()?;
+            let right = self.nested(Self::unary)?;
             Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
@@ -594,22 +580,28 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// call → primary ( "(" arguments? ")" | "." IDENTIFIER )*
-    fn call(&mut self) -> Result<Expr, String> {
+    /// call → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )*
+    fn call(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.primary()?;
         
         loop {
-            if self.check(&Token::LeftParen) {
+            if self.check(&TokenKind::LeftParen) {
                 expr = self.finish_call(expr)?;
-            } else if self.check(&Token::Dot) {
+            } else if self.check(&TokenKind::Dot) {
                 let _dot = self.advance().clone();
-                let name = self.consume(
-                    &Token::Identifier("".to_string()),
-                    "Expect property name after '.'"
-                )?;
+                let name = self.consume_identifier("Expect property name after '.'.")?;
                 expr = Expr::Get {
                     object: Box::new(expr),
-                    name: name.clone(),
+                    name,
+                };
+            } else if self.check(&TokenKind::LeftBracket) {
+                let bracket = self.advance().clone();
+                let index = self.expression()?;
+                self.consume(&TokenKind::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
                 };
             } else {
                 break;
@@ -620,27 +612,27 @@ impl<'a> Parser<'a> {
     }
 
     /// 辅助方法：处理函数调用参数
-    fn finish_call(&mut self, callee: Expr) -> Result<Expr, String> {
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
         let _paren = self.advance();
         let mut arguments = Vec::new();
         
-        if !self.check(&Token::RightParen) {
+        if !self.check(&TokenKind::RightParen) {
             loop {
                 arguments.push(self.expression()?);
                 
-                if !self.check(&Token::Comma) {
+                if !self.check(&TokenKind::Comma) {
                     break;
                 }
                 
                 self.advance();
                 
                 if arguments.len() >= 255 {
-                    return Err(self.error(self.peek(), "Can't have more than 255 arguments"));
+                    return Err(self.error(self.peek(), "Can't have more than 255 arguments."));
                 }
             }
         }
         
-        let paren = self.consume(&Token::RightParen, "Expect ')' after arguments")?;
+        let paren = self.consume(&TokenKind::RightParen, "Expect ')' after arguments.")?;
         Ok(Expr::Call {
             callee: Box::new(callee),
             paren: paren.clone(),
@@ -652,52 +644,51 @@ impl<'a> Parser<'a> {
     ///         | NUMBER | STRING | IDENTIFIER 
     ///         | "(" expression ")"
     ///         | "super" "." IDENTIFIER
-    fn primary(&mut self) -> Result<Expr, String> {
-        match self.peek() {
-            Token::True => {
-                self.advance();
-                Ok(Expr::Literal(Literal::Bool(true)))
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        match &self.peek().kind {
+            TokenKind::True => {
+                let span = self.advance().span;
+                Ok(Expr::Literal { value: Literal::Bool(true), span })
             }
-            Token::False => {
-                self.advance();
-                Ok(Expr::Literal(Literal::Bool(false)))
+            TokenKind::False => {
+                let span = self.advance().span;
+                Ok(Expr::Literal { value: Literal::Bool(false), span })
             }
-            Token::Nil => {
-                self.advance();
-                Ok(Expr::Literal(Literal::Nil))
+            TokenKind::Nil => {
+                let span = self.advance().span;
+                Ok(Expr::Literal { value: Literal::Nil, span })
             }
-            Token::Number(n) => {
+            TokenKind::Number(n) => {
                 let value = *n;
-                self.advance();
-                Ok(Expr::Literal(Literal::Number(value)))
+                let span = self.advance().span;
+                Ok(Expr::Literal { value: Literal::Number(value), span })
             }
-            Token::String(s) => {
+            TokenKind::String(s) => {
                 let value = s.clone();
-                self.advance();
-                Ok(Expr::Literal(Literal::String(value)))
+                let span = self.advance().span;
+                Ok(Expr::Literal { value: Literal::String(value), span })
             }
-            Token::LeftParen => {
+            TokenKind::LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
-                self.consume(&Token::RightParen, "Expect ')' after expression")?;
+                self.consume(&TokenKind::RightParen, "Expect ')' after expression.")?;
                 Ok(Expr::Grouping(Box::new(expr)))
             }
-            Token::This => {
+            TokenKind::This => {
                 let token = self.advance().clone();
                 Ok(Expr::This(token))
             }
-            Token::Super => {
+            TokenKind::Super => {
                 let keyword = self.advance().clone();
-                self.consume(&Token::Dot, "Expect '.' after 'super'")?;
-                let method = self.consume_identifier("Expect superclass method name")?;
-                Ok(Expr::Super { keyword, method })
+                self.consume(&TokenKind::Dot, "Expect '.' after 'super'.")?;
+                let method = self.consume_identifier("Expect superclass method name.")?;
+                Ok(Expr::Super { keyword, method, depth: Cell::new(None) })
             }
-            Token::Identifier(_) => {
+            TokenKind::Identifier(_) => {
                 let token = self.advance().clone();
-                Ok(Expr::Variable(token))
+                Ok(Expr::variable(token))
             }
-            //_ => Err(self.error(self.peek(), "Expect expression")),
-            _ => Err("".to_string()),
+            _ => Err(self.error(self.peek(), "Expect expression.")),
         }
     }
     
@@ -706,22 +697,42 @@ impl<'a> Parser<'a> {
     // 辅助方法框架
     // --------------------------------------------
 
-    fn error(&self, token: &Token, message: &str) -> String {
-        format!("Error at '{}': {}", token.lexeme(), message)
+    /// 进入一层嵌套的语法结构：超过 MAX_NESTING 层时报告语法错误，
+    /// 而不是让递归下降耗尽栈空间
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= MAX_NESTING {
+            // 剩余的输入无法可靠地恢复，记录错误后直接跳到末尾
+            let error = self.error(self.peek(), "Too much nesting.");
+            if !self.too_deep {
+                self.errors.push(error.clone());
+                self.too_deep = true;
+                self.current = self.tokens.len();
+            }
+            return Err(error);
+        }
+        self.depth += 1;
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || parse(self));
+        self.depth -= 1;
+        result
+    }
+
+    fn error(&self, token: &Token, message: &str) -> ParseError {
+        ParseError::new(token, None, message)
     }
 
     fn synchronize(&mut self) {
         self.advance();
         
         while !self.is_at_end() {
-            if let Token::Semicolon = self.previous() {
+            if self.previous().kind == TokenKind::Semicolon {
                 return;
             }
             
-            match self.peek() {
-                Token::Class | Token::Fun | Token::Var | 
-                Token::For | Token::If | Token::While |
-                Token::Print | Token::Return => return,
+            match &self.peek().kind {
+                TokenKind::Class | TokenKind::Fun | TokenKind::Var | 
+                TokenKind::For | TokenKind::If | TokenKind::While |
+                TokenKind::Print | TokenKind::Return |
+                TokenKind::Break | TokenKind::Continue => return,
                 _ => {self.advance();}
             }
         }
@@ -0,0 +1,261 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::ast::{Expr, Stmt};
+use crate::error::ResolveError;
+use crate::token::Token;
+
+/// 当前所在的函数类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum FunctionType {
+    #[default]
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+/// 当前所在的类类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ClassType {
+    #[default]
+    None,
+    Class,
+    Subclass,
+}
+
+/// 静态解析：在执行前遍历语法树，计算每个局部变量引用所在的作用域深度
+///
+/// 深度写回到 `Expr::Variable` / `Expr::Assign` 的 depth 中，
+/// 解释器据此直接定位变量所在的作用域；找不到的变量视为全局变量。
+/// 同时报告只看代码结构就能发现的错误，例如顶层的 return。
+#[derive(Debug, Default)]
+pub struct Resolver {
+    // 局部作用域栈，值表示变量是否已经完成初始化
+    scopes: Vec<HashMap<String, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    // 当前函数中包围着的循环层数，用于检查 break/continue
+    loop_depth: usize,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 解析全部语句，最后一次返回所有静态错误
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), Vec<ResolveError>> {
+        self.resolve_stmts(statements);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_stmts(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) | Stmt::Print(expr) => self.resolve_expr(expr),
+            Stmt::Var { name, initializer } => {//变量声明语句
+                self.declare(name);
+                if let Some(expr) = initializer {
+                    self.resolve_expr(expr);
+                }
+                self.define(name);
+            }
+            Stmt::Block(stmts) => {//块语句
+                self.begin_scope();
+                self.resolve_stmts(stmts);
+                self.end_scope();
+            }
+            Stmt::If { condition, then_branch, else_branch } => {//条件语句
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::While { condition, body, increment } => {//while循环语句
+                self.resolve_expr(condition);
+                self.loop_depth += 1;
+                self.resolve_stmt(body);
+                self.loop_depth -= 1;
+                if let Some(inc) = increment {
+                    self.resolve_expr(inc);
+                }
+            }
+            Stmt::Function { name, params, body } => {//函数声明语句
+                // 先定义函数名，函数体内可以递归调用自己
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(params, body, FunctionType::Function);
+            }
+            Stmt::Return { keyword, value } => {//返回语句
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(expr) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expr(expr);
+                }
+            }
+            Stmt::Break(keyword) => {//break语句
+                if self.loop_depth == 0 {
+                    self.error(keyword, "Can't use 'break' outside of a loop.");
+                }
+            }
+            Stmt::Continue(keyword) => {//continue语句
+                if self.loop_depth == 0 {
+                    self.error(keyword, "Can't use 'continue' outside of a loop.");
+                }
+            }
+            Stmt::Class { name, superclass, methods } => {//类声明语句
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+                self.declare(name);
+                self.define(name);
+                if let Some(superclass) = superclass {
+                    self.current_class = ClassType::Subclass;
+                    if let Expr::Variable { name: super_name, .. } = superclass {
+                        if super_name.lexeme() == name.lexeme() {
+                            self.error(super_name, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.resolve_expr(superclass);
+                    // 有基类时，方法外面多一层只包含 super 的作用域，与解释器一致
+                    self.begin_scope();
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert("super".to_string(), true);
+                    }
+                }
+                // 方法不是变量，只解析参数和方法体
+                for method in methods {
+                    if let Stmt::Function { name: method_name, params, body } = method {
+                        let function_type = if method_name.lexeme() == "init" {
+                            FunctionType::Initializer
+                        } else {
+                            FunctionType::Method
+                        };
+                        self.resolve_function(params, body, function_type);
+                    }
+                }
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+                self.current_class = enclosing_class;
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt], function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+        // 函数体中的 break/continue 不能跳出函数外面的循环
+        let enclosing_loop_depth = std::mem::take(&mut self.loop_depth);
+        // 参数和函数体共用一个作用域，与解释器中函数调用创建的作用域一致
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_stmts(body);
+        self.end_scope();
+        self.loop_depth = enclosing_loop_depth;
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { .. } => {}
+            Expr::Super { keyword, depth, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => self.error(keyword, "Can't use 'super' in a class with no superclass."),
+                    ClassType::Subclass => {}
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expr::This(keyword) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                }
+            }
+            Expr::Variable { name, depth } => {
+                if self.scopes.last().and_then(|scope| scope.get(name.lexeme())) == Some(&false) {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.resolve_local(name, depth);
+            }
+            Expr::Assign { name, value, depth } => {
+                self.resolve_expr(value);
+                self.resolve_local(name, depth);
+            }
+            Expr::Logical { left, right, .. } | Expr::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Call { callee, arguments, .. } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::Grouping(expr) => self.resolve_expr(expr),
+        }
+    }
+
+    /// 从内向外查找变量所在的局部作用域，记录深度；找不到时保持 None（全局变量）
+    fn resolve_local(&mut self, name: &Token, depth: &Cell<Option<usize>>) {
+        let found = self.scopes.iter().rev()
+            .position(|scope| scope.contains_key(name.lexeme()));
+        depth.set(found);
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError::new(token, message));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// 在当前局部作用域中声明变量；全局变量允许重复声明
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.insert(name.lexeme().to_string(), false).is_some() {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme().to_string(), true);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::{RuntimeError, RuntimeErrorKind};
+use crate::intepreter::{NativeFn, Value};
+
+/// 字符串的方法：通过 `s.name` 访问，得到一个绑定了该字符串的内置函数
+///
+/// 所有下标都按字符（Unicode 标量值）计算，而不是按 UTF-8 字节
+pub fn string_method(s: &str, name: &str) -> Option<Value> {
+    let s = s.to_string();
+    let method = match name {
+        // len()：字符个数
+        "len" => native(name, 0, move |_| Ok(Value::Number(s.chars().count() as f64))),
+        // substring(start, end)：下标在 [start, end) 范围内的字符
+        "substring" => native(name, 2, move |args| {
+            let chars: Vec<char> = s.chars().collect();
+            let start = to_index(&args[0])?;
+            let end = to_index(&args[1])?;
+            if start < 0 || end < start || end as usize > chars.len() {
+                return Err(error(RuntimeErrorKind::Index, format!(
+                    "Substring range {}..{} out of range for length {}.", start, end, chars.len())));
+            }
+            Ok(Value::String(chars[start as usize..end as usize].iter().collect()))
+        }),
+        // indexOf(sub)：第一次出现的位置，找不到时为 -1
+        "indexOf" => native(name, 1, move |args| {
+            let sub = to_str(&args[0])?;
+            let index = match s.find(sub) {
+                Some(byte) => s[..byte].chars().count() as f64,
+                None => -1.0,
+            };
+            Ok(Value::Number(index))
+        }),
+        // split(sep)：按分隔符拆分为列表，分隔符为空字符串时拆分为单个字符
+        "split" => native(name, 1, move |args| {
+            let sep = to_str(&args[0])?;
+            let parts: Vec<Value> = if sep.is_empty() {
+                s.chars().map(|ch| Value::String(ch.to_string())).collect()
+            } else {
+                s.split(sep).map(|part| Value::String(part.to_string())).collect()
+            };
+            Ok(Value::List(Rc::new(RefCell::new(parts))))
+        }),
+        "trim" => native(name, 0, move |_| Ok(Value::String(s.trim().to_string()))),
+        "upper" => native(name, 0, move |_| Ok(Value::String(s.to_uppercase()))),
+        "lower" => native(name, 0, move |_| Ok(Value::String(s.to_lowercase()))),
+        // replace(from, to)：替换所有出现的 from
+        "replace" => native(name, 2, move |args| {
+            Ok(Value::String(s.replace(to_str(&args[0])?, to_str(&args[1])?)))
+        }),
+        "startsWith" => native(name, 1, move |args| Ok(Value::Bool(s.starts_with(to_str(&args[0])?)))),
+        "endsWith" => native(name, 1, move |args| Ok(Value::Bool(s.ends_with(to_str(&args[0])?)))),
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// 列表的方法，目前只有 len()
+pub fn list_method(list: &Rc<RefCell<Vec<Value>>>, name: &str) -> Option<Value> {
+    let list = list.clone();
+    match name {
+        "len" => Some(native(name, 0, move |_| Ok(Value::Number(list.borrow().len() as f64)))),
+        _ => None,
+    }
+}
+
+/// 下标访问 `object[index]`：字符串得到单个字符组成的字符串，列表得到对应的元素
+pub fn index(object: &Value, index: &Value) -> Result<Value, RuntimeError> {
+    let i = to_index(index)?;
+    match object {
+        Value::String(s) => {
+            let len = s.chars().count();
+            usize::try_from(i).ok()
+                .and_then(|i| s.chars().nth(i))
+                .map(|ch| Value::String(ch.to_string()))
+                .ok_or_else(|| error(RuntimeErrorKind::Index, format!("String index {} out of range for length {}.", i, len)))
+        }
+        Value::List(list) => {
+            let list = list.borrow();
+            usize::try_from(i).ok()
+                .and_then(|i| list.get(i))
+                .cloned()
+                .ok_or_else(|| error(RuntimeErrorKind::Index, format!("List index {} out of range for length {}.", i, list.len())))
+        }
+        _ => Err(error(RuntimeErrorKind::Type, "Only strings and lists can be indexed.")),
+    }
+}
+
+/// 绑定了接收者的内置函数
+fn native<F>(name: &str, arity: usize, func: F) -> Value
+where
+    F: Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+{
+    Value::Native {
+        name: name.to_string(),
+        arity,
+        func: NativeFn(Rc::new(move |_, args| func(args))),
+    }
+}
+
+/// 下标必须是整数
+fn to_index(value: &Value) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 => Ok(*n as i64),
+        _ => Err(error(RuntimeErrorKind::Type, "Index must be an integer.")),
+    }
+}
+
+fn to_str(value: &Value) -> Result<&str, RuntimeError> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(error(RuntimeErrorKind::Type, "Argument must be a string.")),
+    }
+}
+
+/// 没有位置信息的运行时错误，由调用处补上位置
+fn error(kind: RuntimeErrorKind, message: impl Into<String>) -> RuntimeError {
+    RuntimeError::new(kind, None, message)
+}
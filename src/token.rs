@@ -1,11 +1,30 @@
+use std::fmt;
+
+/// 源代码中的一段位置
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,   // 行号，从 1 开始
+    pub column: usize, // 列号（字符），从 1 开始
+    pub offset: usize, // 字节偏移
+    pub len: usize,    // 字节长度
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}:{}]", self.line, self.column)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 
-pub enum Token {
+pub enum TokenKind {
     // 单字符 token
     LeftParen,    // "("
     RightParen,   // ")"
     LeftBrace,    // "{"
     RightBrace,   // "}"
+    LeftBracket,  // "["
+    RightBracket, // "]"
     Comma,        // ","
     Dot,          // "."
     Minus,        // "-"
@@ -13,7 +32,7 @@ pub enum Token {
     Semicolon,    // ";"
     Slash,        // "/"
     Star,         // "*"
-    
+
     // 可能是两个字符的 token
     Bang,         // "!"
     BangEqual,    // "!="
@@ -23,15 +42,17 @@ pub enum Token {
     GreaterEqual, // ">="
     Less,         // "<"
     LessEqual,    // "<="
-    
+
     // 字面量
     Identifier(String),  // 变量名/函数名等
     String(String),      // 字符串字面量
     Number(f64),         // 数字字面量
-    
+
     // 关键字
     And,         // "and"
+    Break,       // "break"
     Class,       // "class"
+    Continue,    // "continue"
     Else,        // "else"
     False,       // "false"
     Fun,         // "fun"
@@ -46,58 +67,85 @@ pub enum Token {
     True,        // "true"
     Var,         // "var"
     While,       // "while"
-    
+
     // 特殊 token
     Eof,         // 文件结束
 }
 
+impl fmt::Display for TokenKind {
+    /// 输出 token 在源代码中的写法
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Dot => ".",
+            TokenKind::Minus => "-",
+            TokenKind::Plus => "+",
+            TokenKind::Semicolon => ";",
+            TokenKind::Slash => "/",
+            TokenKind::Star => "*",
+            TokenKind::Bang => "!",
+            TokenKind::BangEqual => "!=",
+            TokenKind::Equal => "=",
+            TokenKind::EqualEqual => "==",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::Identifier(name) => return write!(f, "{}", name),
+            TokenKind::String(s) => return write!(f, "\"{}\"", s),
+            TokenKind::Number(n) => return write!(f, "{}", n),
+            TokenKind::And => "and",
+            TokenKind::Break => "break",
+            TokenKind::Class => "class",
+            TokenKind::Continue => "continue",
+            TokenKind::Else => "else",
+            TokenKind::False => "false",
+            TokenKind::Fun => "fun",
+            TokenKind::For => "for",
+            TokenKind::If => "if",
+            TokenKind::Nil => "nil",
+            TokenKind::Or => "or",
+            TokenKind::Print => "print",
+            TokenKind::Return => "return",
+            TokenKind::Super => "super",
+            TokenKind::This => "this",
+            TokenKind::True => "true",
+            TokenKind::Var => "var",
+            TokenKind::While => "while",
+            TokenKind::Eof => "end",
+        };
+        f.write_str(text)
+    }
+}
+
+/// 词法单元：类型 + 源代码中的原文 + 位置
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub lexeme: String,
+    pub span: Span,
+}
+
 impl Token {
+    pub fn new(kind: TokenKind, lexeme: impl Into<String>, span: Span) -> Self {
+        Token { kind, lexeme: lexeme.into(), span }
+    }
+
     pub fn line(&self) -> usize {
-        // 实际实现需要跟踪行号
-        0 // 暂时返回默认值
+        self.span.line
     }
-    
+
+    pub fn column(&self) -> usize {
+        self.span.column
+    }
+
     pub fn lexeme(&self) -> &str {
-        match self {
-            Token::Identifier(s) => s,
-            Token::String(s) => s,
-            Token::Number(n) => Box::leak(n.to_string().into_boxed_str()),
-            Token::LeftParen => "(",
-            Token::RightParen => ")",
-            Token::LeftBrace => "{",
-            Token::RightBrace => "}",
-            Token::Comma => ",",
-            Token::Dot => ".",
-            Token::Minus => "-",
-            Token::Plus => "+",
-            Token::Semicolon => ";",
-            Token::Slash => "/",
-            Token::Star => "*",
-            Token::Bang => "!",
-            Token::BangEqual => "!=",
-            Token::Equal => "=",
-            Token::EqualEqual => "==",
-            Token::Greater => ">",
-            Token::GreaterEqual => ">=",
-            Token::Less => "<",
-            Token::LessEqual => "<=",
-            Token::And => "and",
-            Token::Class => "class",
-            Token::Else => "else",
-            Token::False => "false",
-            Token::Fun => "fun",
-            Token::For => "for",
-            Token::If => "if",
-            Token::Nil => "nil",
-            Token::Or => "or",
-            Token::Print => "print",
-            Token::Return => "return",
-            Token::Super => "super",
-            Token::This => "this",
-            Token::True => "true",
-            Token::Var => "var",
-            Token::While => "while",
-            Token::Eof => "EOF",
-        }
+        &self.lexeme
     }
 }
@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use lox_interpreter::{Expected, Interpreter, LoxError, RuntimeError, RuntimeErrorKind, Value};
+use lox_interpreter::token::TokenKind;
+
+/// 注册一个把参数记录下来的内置函数，用于在测试中观察 Lox 代码的结果
+fn recorder(interpreter: &mut Interpreter) -> Rc<RefCell<Vec<String>>> {
+    let recorded = Rc::new(RefCell::new(Vec::new()));
+    let sink = recorded.clone();
+    interpreter.define_native("record", 1, move |_, args| {
+        sink.borrow_mut().push(args[0].to_lox_string());
+        Ok(Value::Nil)
+    });
+    recorded
+}
+
+fn runtime_error(result: Result<(), LoxError>) -> RuntimeError {
+    match result {
+        Err(LoxError::Runtime(err)) => err,
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn host_native_is_callable_from_lox() {
+    let mut interpreter = Interpreter::new();
+    let recorded = recorder(&mut interpreter);
+    interpreter.define_native("twice", 1, |_, args| match &args[0] {
+        Value::Number(n) => Ok(Value::Number(n * 2.0)),
+        _ => Err(RuntimeError::new(RuntimeErrorKind::Native, None, "twice() expects a number.")),
+    });
+
+    interpreter.run_source("record(twice(21)); record(twice);").unwrap();
+    assert_eq!(*recorded.borrow(), ["42", "<native fn>"]);
+}
+
+#[test]
+fn host_native_arity_is_checked() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_native("pair", 2, |_, _| Ok(Value::Nil));
+
+    let err = runtime_error(interpreter.run_source("pair(1);"));
+    assert_eq!(err.kind, RuntimeErrorKind::Arity);
+    assert_eq!(err.message, "Expected 2 arguments but got 1.");
+}
+
+#[test]
+fn host_native_error_is_reported_at_call_site() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_native("fail", 0, |_, _| {
+        Err(RuntimeError::new(RuntimeErrorKind::Native, None, "fail() always fails."))
+    });
+
+    let source = "fun wrapper() {\n  return fail();\n}\nwrapper();\n";
+    let err = runtime_error(interpreter.run_source(source));
+    assert_eq!(err.kind, RuntimeErrorKind::Native);
+    assert_eq!(err.message, "fail() always fails.");
+    assert_eq!((err.line, err.column), (2, 15));
+    let trace: Vec<&str> = err.trace.iter().map(|frame| frame.function.as_str()).collect();
+    assert_eq!(trace, ["fail", "wrapper"]);
+}
+
+fn global(interpreter: &Interpreter, name: &str) -> Option<String> {
+    interpreter.globals().into_iter()
+        .find(|(global, _)| global == name)
+        .map(|(_, value)| value)
+}
+
+#[test]
+fn interpreters_are_independent() {
+    let mut first = Interpreter::new();
+    let mut second = Interpreter::new();
+    first.run_source("var shared = \"first\"; fun f() { return undefined; }").unwrap();
+    second.run_source("var shared = \"second\";").unwrap();
+
+    // 第一个解释器在函数调用中出错，不影响第二个解释器，也不影响它自己之后的执行
+    let err = runtime_error(first.run_source("f();"));
+    assert_eq!(err.kind, RuntimeErrorKind::UndefinedVariable);
+    assert_eq!(global(&second, "shared").as_deref(), Some("second"));
+    assert_eq!(global(&second, "f"), None);
+
+    second.run_source("shared = shared + \"!\";").unwrap();
+    first.run_source("shared = shared + \"?\";").unwrap();
+    assert_eq!(global(&first, "shared").as_deref(), Some("first?"));
+    assert_eq!(global(&second, "shared").as_deref(), Some("second!"));
+
+    // 出错时的调用栈不会残留到下一次错误中
+    let err = runtime_error(first.run_source("undefined;"));
+    assert!(err.trace.is_empty());
+}
+
+#[test]
+fn interpreters_on_different_threads_do_not_interfere() {
+    let handles: Vec<_> = (0..4)
+        .map(|i| std::thread::spawn(move || {
+            let mut interpreter = Interpreter::new();
+            if i % 2 == 0 {
+                runtime_error(interpreter.run_source("nope;"));
+            }
+            interpreter.run_source(&format!("var n = {}; n = n * 10;", i)).unwrap();
+            global(&interpreter, "n")
+        }))
+        .collect();
+    let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(results, [Some("0".to_string()), Some("10".to_string()), Some("20".to_string()), Some("30".to_string())]);
+}
+
+#[test]
+fn deep_recursion_reports_stack_overflow() {
+    // 测试线程的栈只有 2MB，递归调用同样要以运行时错误结束，而不是让进程崩溃
+    let mut interpreter = Interpreter::new();
+    let recorded = recorder(&mut interpreter);
+    interpreter.run_source("fun f(n) { if (n == 0) return 0; return f(n - 1) + 1; }\nrecord(f(200));").unwrap();
+    assert_eq!(*recorded.borrow(), ["200"]);
+
+    let err = runtime_error(interpreter.run_source("fun g() { g(); }\ng();"));
+    assert_eq!(err.kind, RuntimeErrorKind::StackOverflow);
+    assert_eq!(err.message, "Stack overflow.");
+    assert_eq!((err.line, err.column), (1, 13));
+    assert!(err.trace.iter().all(|frame| frame.function == "g"));
+
+    // 出错后调用栈被清空，可以继续正常调用
+    interpreter.run_source("record(f(10));").unwrap();
+    assert_eq!(*recorded.borrow(), ["200", "10"]);
+}
+
+#[test]
+fn debug_output_does_not_follow_closures() {
+    // 函数的 closure 中保存着函数本身，调试输出不能沿着它递归下去
+    let mut interpreter = Interpreter::new();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let sink = seen.clone();
+    interpreter.define_native("inspect", 1, move |_, args| {
+        sink.borrow_mut().push(format!("{:?}", args[0]));
+        Ok(Value::Nil)
+    });
+
+    let source = "fun f() {}\nclass A { m() {} }\nvar a = A();\na.self = a;\ninspect(f); inspect(a.m); inspect(a); inspect(clock);";
+    interpreter.run_source(source).unwrap();
+    assert_eq!(*seen.borrow(), ["<fn f>", "<fn m>", "<A instance>", "<native fn clock>"]);
+
+    let debug = format!("{:?}", interpreter);
+    assert!(debug.starts_with("Interpreter { globals: Environment { names: ["), "{}", debug);
+    assert!(debug.contains("\"f\""), "{}", debug);
+}
+
+#[test]
+fn parse_errors_quote_the_source() {
+    let mut interpreter = Interpreter::new();
+    let errors = match interpreter.run_source("var 1.50 = 2;\nprint (1;") {
+        Err(LoxError::Parse(errors)) => errors,
+        other => panic!("expected parse errors, got {:?}", other),
+    };
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].found.as_deref(), Some("1.50"));
+    assert_eq!(errors[0].expected, Some(Expected::Identifier));
+    assert_eq!(errors[0].to_string(), "[line 1:5] Error at '1.50': Expect variable name.");
+    assert_eq!(errors[1].expected, Some(Expected::Token(TokenKind::RightParen)));
+    assert_eq!(errors[1].to_string(), "[line 2:9] Error at ';': Expect ')' after expression.");
+}
+
+#[test]
+fn deep_nesting_is_a_parse_error() {
+    // 嵌套层数在限制以内时，语法分析、静态解析和执行都不会耗尽测试线程的栈
+    let mut interpreter = Interpreter::new();
+    let recorded = recorder(&mut interpreter);
+    let parens = format!("fun f() {{ return {}1{}; }}\nrecord(f());", "(".repeat(250), ")".repeat(250));
+    interpreter.run_source(&parens).unwrap();
+    let blocks = format!("{}record(-{}2);{}", "{".repeat(100), "-".repeat(50), "}".repeat(100));
+    interpreter.run_source(&blocks).unwrap();
+    assert_eq!(*recorded.borrow(), ["1", "-2"]);
+
+    // 超过限制时只报告一个语法错误，不会因为外层缺少 ')' 或 '}' 连带报错
+    for source in [format!("print {}1{};", "(".repeat(300), ")".repeat(300)), "{".repeat(300)] {
+        let errors = match interpreter.run_source(&source) {
+            Err(LoxError::Parse(errors)) => errors,
+            other => panic!("expected parse errors, got {:?}", other),
+        };
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].message, "Too much nesting.");
+    }
+}